@@ -1,52 +1,66 @@
-use std::io;
+use std::error::Error;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
 use biam::utils;
-use biam::local_search::LocalSearch;
+use biam::solver::{self, SolveResult};
 
 
-#[allow(non_snake_case)]
-fn main() -> io::Result<()> {
+fn main() -> Result<(), Box<dyn Error>> {
     // Measurement variables
     let mut time_start;
     let mut avg_time: f64;
 
-    let instances = vec!["data/a280.txt", "data/berlin52.txt", "data/rat99.txt"];
-    let algorithms = vec!["greedy", "steepest"];
+    let instances = ["data/a280.txt", "data/berlin52.txt", "data/rat99.txt"];
+    let algorithms = ["greedy", "steepest", "lin_kernighan", "nearest_neighbour_kd_tree"];
+    // Constructions ignore the initial tour, their own tour is both their initial and final result
+    let constructions = ["nearest_neighbour_kd_tree"];
     let runs = 500;
 
     for path in &instances {
-        let instance_name = path.split("/").last().unwrap().split(".").next().unwrap();
+        let instance = utils::read_instance(path)?;
+        let instance_name = instance.name.as_str();
         println!("{:?}", instance_name);
-        let distance_matrix = utils::read_instance(&path)?;
-        let mut solver_LS = LocalSearch::new(distance_matrix.clone());
+        let distance_matrix = &instance.distance_matrix;
+        let lower_bound = utils::lower_bound(&instance);
 
         for algorithm_name in &algorithms {
-            let mut init_distances = Vec::new();
+            let Some(mut solver) = solver::create_solver(algorithm_name, &instance) else { continue; };
+            let is_construction = constructions.contains(algorithm_name);
+
             let mut elapsed_time = Vec::new();
-            let mut distances = Vec::new();
-            let mut solutions = Vec::new();
-            let mut steps = Vec::new();
-            let mut evaluated = Vec::new();
-            for _ in 0..runs {
-                let init_sol = utils::random_permutation(distance_matrix.len());
-                init_distances.push(utils::calculate_tour_distance(&init_sol, &distance_matrix).unwrap());
+            let mut init_results = Vec::new();
+            let mut results = Vec::new();
+            for run in 0..runs {
+                solver.set_seed(run);
+                if is_construction {
+                    time_start = std::time::Instant::now();
+                    let result = solver.solve()?;
+                    elapsed_time.push(time_start.elapsed().as_millis());
+                    init_results.push(result.clone());
+                    results.push(result);
+                    continue;
+                }
+                // The solver starts from exactly this tour
+                let init_sol = utils::random_permutation(distance_matrix.len(), &mut StdRng::seed_from_u64(run));
+                init_results.push(SolveResult {
+                    distance: utils::calculate_tour_distance(&init_sol, distance_matrix).unwrap(),
+                    tour: init_sol.clone(),
+                    steps: 0,
+                    evaluated: 0,
+                    seed: run,
+                });
 
                 time_start = std::time::Instant::now();
-                let (solution, distance, step, eval) = match *algorithm_name {
-                    "greedy" => solver_LS.greedy().unwrap(),
-                    "steepest" => solver_LS.steepest().unwrap(),
-                    _ => panic!("Unknown algorithm"),
-                };
+                let result = solver.solve_from(Some(init_sol))?;
                 elapsed_time.push(time_start.elapsed().as_millis());
-                distances.push(distance);
-                solutions.push(solution);
-                steps.push(step);
-                evaluated.push(eval);
+                results.push(result);
             }
             // Save initial solutions fitenesses as results/init_final/{instance_name}/init_{algorithm_name}
-            utils::save_solution(&format!("init_final/{}", instance_name), &format!("init_{}", algorithm_name), &solutions, &init_distances, &elapsed_time, &steps, &evaluated);
+            utils::save_solution(&format!("init_final/{}", instance_name), &format!("init_{}", algorithm_name), &solver.metadata(), &init_results, &elapsed_time, Some(lower_bound));
             // Save final solutions fitenesses as results/init_final/{instance_name}/final_{algorithm_name}
-            utils::save_solution(&format!("init_final/{}", instance_name), &format!("final_{}", algorithm_name), &solutions, &distances, &elapsed_time, &steps, &evaluated);
+            utils::save_solution(&format!("init_final/{}", instance_name), &format!("final_{}", algorithm_name), &solver.metadata(), &results, &elapsed_time, Some(lower_bound));
             avg_time = elapsed_time.iter().sum::<u128>() as f64 / elapsed_time.len() as f64;
             println!("\t{:?}: {:?}", algorithm_name, avg_time);
         }
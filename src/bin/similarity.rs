@@ -1,61 +1,39 @@
-use std::io;
+use std::error::Error;
 
 use biam::utils;
+use biam::solver;
+use biam::termination::Termination;
 
-use biam::local_search::LocalSearch;
-use biam::random::Random;
-use biam::simulated_annealing::SimulatedAnnealing;
-use biam::tabu_search::TabuSearch;
 
-
-#[allow(non_snake_case)]
-fn main() -> io::Result<()> {
+fn main() -> Result<(), Box<dyn Error>> {
     // Measurement variables
     let mut time_start;
-    let mut avg_time: f64 = 0.0;
 
-    let instances = vec!["data/a280.txt", "data/rat195.txt"];
-    let algorithms = vec!["greedy", "steepest", "random_search", "random_walk", "heuristic", "simulated_annealing", "tabu_search"];
+    let instances = ["data/a280.txt", "data/rat195.txt"];
     let runs = 101;
+    // The algorithms that only stop on a budget get the same wall-clock budget, the others stop on their own
+    let termination = Termination::new().with_time_limit(1000.0);
 
     for path in &instances {
-        let instance_name = path.split("/").last().unwrap().split(".").next().unwrap();
+        let instance = utils::read_instance(path)?;
+        let instance_name = instance.name.as_str();
         println!("{:?}", instance_name);
-        let distance_matrix = utils::read_instance(&path)?;
-        let mut solver_LS = LocalSearch::new(distance_matrix.clone());
-        let mut solver_R = Random::new(distance_matrix.clone());
-        let mut solver_SA = SimulatedAnnealing::new(distance_matrix.clone());
-        let mut solver_TS = TabuSearch::new(distance_matrix.clone(), None);
-        solver_SA.determine_initial_temperature();
+        let lower_bound = utils::lower_bound(&instance);
 
-        for algorithm_name in &algorithms {
+        for algorithm_name in solver::ALGORITHMS {
+            // Skip the algorithms the instance cannot run, e.g. without coordinates
+            let Some(mut solver) = solver::create_solver(algorithm_name, &instance) else { continue; };
+            if solver::BUDGETED.contains(&algorithm_name) {
+                solver.set_termination(termination.clone());
+            }
             for run in 0..runs {
-                let mut elapsed_time = Vec::new();
-                let mut distances = Vec::new();
-                let mut solutions = Vec::new();
-                let mut steps = Vec::new();
-                let mut evaluated = Vec::new();
+                solver.set_seed(run);
 
                 time_start = std::time::Instant::now();
-                let (solution, distance, step, eval) = match *algorithm_name {
-                    "greedy" => solver_LS.greedy().unwrap(),
-                    "steepest" => solver_LS.steepest().unwrap(),
-                    "random_search" => solver_R.search(avg_time).unwrap(),
-                    "random_walk" => solver_R.walk(avg_time).unwrap(),
-                    "heuristic" => solver_LS.heuristic().unwrap(),
-                    "simulated_annealing" => solver_SA.run().unwrap(),
-                    "tabu_search" => solver_TS.run().unwrap(),
-                    _ => panic!("Unknown algorithm"),
-                };
+                let result = solver.solve()?;
                 let runtime = time_start.elapsed().as_millis();
-                avg_time = runtime as f64;
-                elapsed_time.push(runtime);
-                distances.push(distance);
-                solutions.push(solution);
-                steps.push(step);
-                evaluated.push(eval);
                 // Save every solution as resuluts/similarity/{instance_name}/{algorithm_name}/{run}
-                utils::save_solution(&format!("similarity/{}/{}", instance_name, algorithm_name), &run.to_string(), &solutions, &distances, &elapsed_time, &steps, &evaluated);
+                utils::save_solution(&format!("similarity/{}/{}", instance_name, algorithm_name), &run.to_string(), &solver.metadata(), &[result], &[runtime], Some(lower_bound));
             }
             println!("\t{:?}", algorithm_name);
         }
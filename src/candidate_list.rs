@@ -0,0 +1,176 @@
+use crate::distance::{DistanceMatrix, Metric};
+use crate::instance::Coordinate;
+use crate::kd_tree::KdTree;
+
+
+/**
+ * CandidateList struct
+ *
+ * For every node, a short list of promising neighbours sorted by increasing
+ * distance. Moves are only tried if they introduce an edge to a candidate.
+ *
+ * @field neighbours: The candidates of every node
+ */
+#[derive(Debug, Clone)]
+pub struct CandidateList {
+    neighbours: Vec<Vec<u32>>,
+}
+
+impl CandidateList {
+    /**
+     * Build the lists of the k nearest neighbours of every node.
+     *
+     * @param distance_matrix: The distance matrix of the TSP problem
+     * @param k: The number of candidates per node, capped at n - 1
+     * @return: The candidate lists
+     */
+    pub fn nearest(distance_matrix: &DistanceMatrix, k: usize) -> CandidateList {
+        let n = distance_matrix.len();
+        let k = k.min(n.saturating_sub(1));
+        let neighbours = (0..n)
+            .map(|i| {
+                let mut others: Vec<u32> = (0..n as u32).filter(|&j| j as usize != i).collect();
+                let by_distance = |a: &u32, b: &u32| {
+                    distance_matrix.get(i, *a as usize).total_cmp(&distance_matrix.get(i, *b as usize))
+                };
+                if k < others.len() {
+                    others.select_nth_unstable_by(k, by_distance);
+                    others.truncate(k);
+                }
+                others.sort_by(by_distance);
+                others
+            })
+            .collect();
+        CandidateList { neighbours }
+    }
+
+    /**
+     * Build the lists of the k nearest neighbours of every node, searched in
+     * a k-d tree instead of a full row of the distance matrix. The tree uses
+     * the planar Euclidean distance between the coordinates, so the other
+     * metrics fall back to the full rows. The lists are sorted by the
+     * distance matrix.
+     *
+     * @param coordinates: The coordinates of the nodes
+     * @param metric: The metric of the distance matrix
+     * @param distance_matrix: The distance matrix of the TSP problem
+     * @param k: The number of candidates per node, capped at n - 1
+     * @return: The candidate lists
+     */
+    pub fn nearest_kd_tree(coordinates: &[Coordinate], metric: Metric, distance_matrix: &DistanceMatrix, k: usize) -> CandidateList {
+        if !metric.is_planar_euclidean() {
+            return CandidateList::nearest(distance_matrix, k);
+        }
+        let tree = KdTree::new(coordinates);
+        let neighbours = coordinates
+            .iter()
+            .enumerate()
+            .map(|(i, coordinate)| {
+                let mut others = tree.nearest(coordinate, k, |j| j as usize == i);
+                others.sort_by(|a, b| distance_matrix.get(i, *a as usize).total_cmp(&distance_matrix.get(i, *b as usize)));
+                others
+            })
+            .collect();
+        CandidateList { neighbours }
+    }
+
+    /**
+     * Build the lists from the k / 4 nearest neighbours in each quadrant
+     * around every node, filled up with the nearest remaining ones. Unlike
+     * the plain nearest neighbours, they also link clusters far apart.
+     *
+     * @param coordinates: The coordinates of the nodes
+     * @param distance_matrix: The distance matrix of the TSP problem
+     * @param k: The number of candidates per node, capped at n - 1
+     * @return: The candidate lists
+     */
+    pub fn quadrant(coordinates: &[Coordinate], distance_matrix: &DistanceMatrix, k: usize) -> CandidateList {
+        let n = coordinates.len();
+        let k = k.min(n.saturating_sub(1));
+        let neighbours = (0..n)
+            .map(|i| {
+                let mut others: Vec<u32> = (0..n as u32).filter(|&j| j as usize != i).collect();
+                others.sort_by(|a, b| distance_matrix.get(i, *a as usize).total_cmp(&distance_matrix.get(i, *b as usize)));
+
+                let mut taken = vec![false; others.len()];
+                let mut per_quadrant = [0; 4];
+                for (index, &j) in others.iter().enumerate() {
+                    let (dx, dy) = (coordinates[j as usize].x - coordinates[i].x, coordinates[j as usize].y - coordinates[i].y);
+                    let quadrant = match (dx >= 0.0, dy >= 0.0) {
+                        (true, true) => 0,
+                        (false, true) => 1,
+                        (false, false) => 2,
+                        (true, false) => 3,
+                    };
+                    if per_quadrant[quadrant] < k / 4 {
+                        per_quadrant[quadrant] += 1;
+                        taken[index] = true;
+                    }
+                }
+                let mut remaining = k - taken.iter().filter(|&&t| t).count();
+                for t in taken.iter_mut() {
+                    if remaining == 0 { break; }
+                    if !*t {
+                        *t = true;
+                        remaining -= 1;
+                    }
+                }
+                others.into_iter().zip(taken).filter(|&(_, t)| t).map(|(j, _)| j).collect()
+            })
+            .collect();
+        CandidateList { neighbours }
+    }
+
+    /**
+     * The candidates of a node, nearest first.
+     *
+     * @param node: The node
+     * @return: The candidates
+     */
+    #[inline]
+    pub fn get(&self, node: usize) -> &[u32] {
+        &self.neighbours[node]
+    }
+
+    /**
+     * The largest number of candidates of a node.
+     */
+    pub fn k(&self) -> usize {
+        self.neighbours.iter().map(Vec::len).max().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::Instance;
+
+    /**
+     * The distances from every node to its candidates, ties make the nodes themselves ambiguous.
+     */
+    fn candidate_distances(candidates: &CandidateList, distance_matrix: &DistanceMatrix) -> Vec<Vec<f32>> {
+        (0..distance_matrix.len())
+            .map(|i| candidates.get(i).iter().map(|&j| distance_matrix.get(i, j as usize)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn kd_tree_finds_the_nearest_neighbours() {
+        let instance = Instance::from_file("data/berlin52.txt").unwrap();
+        let distance_matrix = &instance.distance_matrix;
+        let nearest = CandidateList::nearest(distance_matrix, 8);
+        let kd_tree = CandidateList::nearest_kd_tree(&instance.coordinates, instance.metric.unwrap(), distance_matrix, 8);
+        assert_eq!(candidate_distances(&kd_tree, distance_matrix), candidate_distances(&nearest, distance_matrix));
+    }
+
+    #[test]
+    fn kd_tree_falls_back_on_geographical_coordinates() {
+        // Across the pole the first city is nearer the second than the third, unlike on the plane
+        let text = "NAME: geo\nTYPE: TSP\nDIMENSION: 4\nEDGE_WEIGHT_TYPE: GEO\nNODE_COORD_SECTION\n1 85.0 0.0\n2 85.0 180.0\n3 70.0 0.0\n4 0.0 0.0\nEOF\n";
+        let instance = Instance::parse(text.as_bytes()).unwrap();
+        let distance_matrix = &instance.distance_matrix;
+        let kd_tree = CandidateList::nearest_kd_tree(&instance.coordinates, instance.metric.unwrap(), distance_matrix, 1);
+        assert_eq!(kd_tree.get(0), CandidateList::nearest(distance_matrix, 1).get(0));
+        assert_eq!(kd_tree.get(0), &[1]);
+    }
+}
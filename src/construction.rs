@@ -0,0 +1,814 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::json;
+
+use crate::utils;
+use crate::distance::DistanceMatrix;
+use crate::instance::Coordinate;
+use crate::kd_tree::KdTree;
+use crate::solver::{SolveResult, Solver};
+use crate::spanning_tree::SpanningTree;
+
+/**
+ * The algorithm run by Construction as a Solver.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstructionMethod {
+    NearestNeighbour,
+    NearestNeighbourAllStarts,
+    Grasp,
+    NearestNeighbourKdTree,
+    NearestInsertion,
+    FarthestInsertion,
+    CheapestInsertion,
+    RandomInsertion,
+    GreedyEdge,
+    Savings,
+    MstDoubling,
+    ChristofidesGreedyMatching,
+}
+
+/**
+ * Construction struct
+ *
+ * Builds a tour from scratch instead of improving one, so the initial tour
+ * and the termination are ignored. The tour can be handed to an improvement
+ * method through solve_from. Every distance or insertion cost looked at while
+ * choosing the next city counts as an evaluation, and every added city as a step.
+ *
+ * @field distance_matrix: The distance matrix of the TSP problem
+ * @field n: The number of nodes
+ * @field method: The algorithm run by solve()
+ * @field coordinates: The coordinates of the nodes, required by the k-d tree method
+ * @field grasp_size: The number of nearest cities GRASP picks the next city from
+ * @field seed: The seed of the random number generator
+ * @field rng: The random number generator
+ */
+pub struct Construction {
+    pub distance_matrix: DistanceMatrix,
+    pub n: usize,
+    pub method: ConstructionMethod,
+    pub coordinates: Option<Vec<Coordinate>>,
+    pub grasp_size: usize,
+    pub seed: u64,
+    rng: StdRng,
+}
+
+impl Construction {
+    /**
+     * Create a new Construction instance
+     *
+     * @param distance_matrix: The distance matrix of the TSP problem
+     * @return: A new Construction instance
+     */
+    pub fn new(distance_matrix: DistanceMatrix) -> Construction {
+        let n = distance_matrix.len();
+        Construction {
+            distance_matrix,
+            n,
+            method: ConstructionMethod::NearestNeighbour,
+            coordinates: None,
+            grasp_size: 3,
+            seed: 0,
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+
+    /**
+     * Set the algorithm run by solve()
+     *
+     * @param method: The algorithm
+     * @return: The Construction instance
+     */
+    pub fn with_method(mut self, method: ConstructionMethod) -> Construction {
+        self.method = method;
+        self
+    }
+
+    /**
+     * Set the coordinates the k-d tree is built on
+     *
+     * @param coordinates: The coordinates of the nodes
+     * @return: The Construction instance
+     */
+    pub fn with_coordinates(mut self, coordinates: Vec<Coordinate>) -> Construction {
+        self.coordinates = Some(coordinates);
+        self
+    }
+
+    /**
+     * Set the number of nearest cities GRASP picks the next city from
+     *
+     * @param grasp_size: The number of cities, 1 gives the plain nearest neighbour
+     * @return: The Construction instance
+     */
+    pub fn with_grasp_size(mut self, grasp_size: usize) -> Construction {
+        self.grasp_size = grasp_size.max(1);
+        self
+    }
+
+    /**
+     * Build a tour with the nearest neighbour heuristic from a random city
+     *
+     * @return: The tour and its distance
+     */
+    pub fn nearest_neighbour(&mut self) -> Result<SolveResult, &'static str> {
+        self.randomised_nearest_neighbour(1)
+    }
+
+    /**
+     * Build a tour with the nearest neighbour heuristic from every city and
+     * keep the shortest. Costs n times as much as a single start.
+     *
+     * @return: The best tour found and its distance
+     */
+    pub fn nearest_neighbour_all_starts(&mut self) -> Result<SolveResult, &'static str> {
+        if self.n == 0 { return Err("Cannot build a tour without cities"); }
+        let mut best: Option<(Vec<u32>, f64)> = None;
+        let mut evaluated: u32 = 0;
+        for start in 0..self.n {
+            let (tour, start_evaluated) = nearest_neighbour(&self.distance_matrix, start, 1, &mut self.rng);
+            evaluated = evaluated.saturating_add(start_evaluated);
+            let length = utils::tour_length(&tour, &self.distance_matrix);
+            if best.as_ref().is_none_or(|(_, best_length)| length < *best_length) {
+                best = Some((tour, length));
+            }
+        }
+        let (tour, length) = best.unwrap();
+        Ok(SolveResult { tour, distance: length as f32, steps: self.n as u32 - 1, evaluated, seed: self.seed })
+    }
+
+    /**
+     * Build a tour GRASP style: from a random city, go to one of the
+     * grasp_size nearest unvisited cities, drawn uniformly.
+     *
+     * @return: The tour and its distance
+     */
+    pub fn grasp(&mut self) -> Result<SolveResult, &'static str> {
+        self.randomised_nearest_neighbour(self.grasp_size)
+    }
+
+    fn randomised_nearest_neighbour(&mut self, rcl_size: usize) -> Result<SolveResult, &'static str> {
+        if self.n == 0 { return Err("Cannot build a tour without cities"); }
+        let start = self.rng.gen_range(0..self.n);
+        let (tour, evaluated) = nearest_neighbour(&self.distance_matrix, start, rcl_size, &mut self.rng);
+        let distance = utils::calculate_tour_distance(&tour, &self.distance_matrix).unwrap();
+        Ok(SolveResult { tour, distance, steps: self.n as u32 - 1, evaluated, seed: self.seed })
+    }
+
+    /**
+     * Build a tour with the nearest neighbour heuristic from a random city,
+     * looking the nearest unvisited city up in a k-d tree. The tree uses the
+     * planar Euclidean distance between the coordinates, which picks the same
+     * city as the distance matrix only for the metrics of
+     * Metric::is_planar_euclidean.
+     *
+     * @return: The tour and its distance
+     */
+    pub fn nearest_neighbour_kd_tree(&mut self) -> Result<SolveResult, &'static str> {
+        if self.n == 0 { return Err("Cannot build a tour without cities"); }
+        let coordinates = match &self.coordinates {
+            Some(coordinates) if coordinates.len() == self.n => coordinates,
+            _ => return Err("The k-d tree nearest neighbour needs the coordinates of every node"),
+        };
+        let mut tree = KdTree::new(coordinates);
+        let mut evaluated = 0;
+
+        let mut current = self.rng.gen_range(0..self.n) as u32;
+        let mut tour = Vec::with_capacity(self.n);
+        tour.push(current);
+        tree.remove(current);
+        while tour.len() < self.n {
+            current = tree.nearest_counted(&coordinates[current as usize], 1, |_| false, &mut evaluated)[0];
+            tour.push(current);
+            tree.remove(current);
+        }
+        let distance = utils::calculate_tour_distance(&tour, &self.distance_matrix).unwrap();
+        Ok(SolveResult { tour, distance, steps: self.n as u32 - 1, evaluated, seed: self.seed })
+    }
+
+    /**
+     * Build a tour by growing a cycle from a random city, one city at a time.
+     * Nearest, farthest and random insertion choose the city to insert by
+     * its distance to the cycle, or at random, and put it where it lengthens
+     * the cycle the least. Cheapest insertion chooses the city and the place
+     * with the smallest increase together.
+     *
+     * @return: The tour and its distance
+     */
+    pub fn insertion(&mut self) -> Result<SolveResult, &'static str> {
+        if self.n == 0 { return Err("Cannot build a tour without cities"); }
+        let start = self.rng.gen_range(0..self.n);
+        let (tour, evaluated) = match self.method {
+            ConstructionMethod::CheapestInsertion => cheapest_insertion(&self.distance_matrix, start),
+            _ => self.selected_insertion(start),
+        };
+        let distance = utils::calculate_tour_distance(&tour, &self.distance_matrix).unwrap();
+        Ok(SolveResult { tour, distance, steps: self.n as u32 - 1, evaluated, seed: self.seed })
+    }
+
+    /**
+     * Build a tour from the shortest edges: the edges are taken by increasing
+     * length, skipping those that would give a city two successors or two
+     * predecessors, or close a cycle too early. Large instances start with
+     * the edges to the nearest cities only, see link_path.
+     *
+     * @return: The tour and its distance
+     */
+    pub fn greedy_edge(&mut self) -> Result<SolveResult, &'static str> {
+        if self.n == 0 { return Err("Cannot build a tour without cities"); }
+        let cities: Vec<usize> = (0..self.n).collect();
+        let (tour, evaluated) = link_path(&self.distance_matrix, &cities, |from, to| self.distance_matrix.get(from, to));
+        let distance = utils::calculate_tour_distance(&tour, &self.distance_matrix).unwrap();
+        Ok(SolveResult { tour, distance, steps: self.n as u32 - 1, evaluated, seed: self.seed })
+    }
+
+    /**
+     * Build a tour with the Clarke-Wright savings heuristic. Every city
+     * starts on its own round trip from a random hub, and the round trips
+     * are merged by the largest savings d(i, hub) + d(hub, j) - d(i, j) of
+     * going from i to j directly, under the same rules as the greedy edge.
+     *
+     * @return: The tour and its distance
+     */
+    pub fn savings(&mut self) -> Result<SolveResult, &'static str> {
+        if self.n == 0 { return Err("Cannot build a tour without cities"); }
+        let hub = self.rng.gen_range(0..self.n);
+        let cities: Vec<usize> = (0..self.n).filter(|&city| city != hub).collect();
+        // The largest savings first
+        let (path, evaluated) = link_path(&self.distance_matrix, &cities, |from, to| {
+            -(self.distance_matrix.get(from, hub) + self.distance_matrix.get(hub, to) - self.distance_matrix.get(from, to))
+        });
+        let mut tour = vec![hub as u32];
+        tour.extend(path);
+        let distance = utils::calculate_tour_distance(&tour, &self.distance_matrix).unwrap();
+        Ok(SolveResult { tour, distance, steps: self.n as u32 - 1, evaluated, seed: self.seed })
+    }
+
+    /**
+     * Build a tour by walking around a minimum spanning tree, rooted at a
+     * random city, and skipping the cities already visited. On a metric
+     * symmetric instance the tour is at most twice as long as the optimum:
+     * the walk uses every tree edge twice and shortcuts never lengthen it.
+     *
+     * @return: The tour and its distance
+     */
+    pub fn mst_doubling(&mut self) -> Result<SolveResult, &'static str> {
+        if self.n == 0 { return Err("Cannot build a tour without cities"); }
+        let root = self.rng.gen_range(0..self.n);
+        let tree = SpanningTree::minimum(&self.distance_matrix, root);
+        let evaluated = u32::try_from(self.n * (self.n - 1) / 2).unwrap_or(u32::MAX);
+
+        let tour = tree.preorder();
+        let distance = utils::calculate_tour_distance(&tour, &self.distance_matrix).unwrap();
+        Ok(SolveResult { tour, distance, steps: self.n as u32 - 1, evaluated, seed: self.seed })
+    }
+
+    /**
+     * Build a tour with Christofides' heuristic, except that the cities of
+     * odd degree in the minimum spanning tree are paired by a greedy
+     * matching, shortest pairs first, instead of a minimum-weight perfect
+     * matching. The 3/2 guarantee of Christofides needs the exact matching,
+     * a greedy one can weigh more than a constant times the optimum, so
+     * this variant has no worst-case ratio. The Euler tour of the tree and
+     * the matching, started at a random city, is shortcut to a tour.
+     *
+     * @return: The tour and its distance
+     */
+    pub fn christofides_greedy_matching(&mut self) -> Result<SolveResult, &'static str> {
+        if self.n == 0 { return Err("Cannot build a tour without cities"); }
+        let root = self.rng.gen_range(0..self.n);
+        let tree = SpanningTree::minimum(&self.distance_matrix, root);
+        let mut evaluated = u32::try_from(self.n * (self.n - 1) / 2).unwrap_or(u32::MAX);
+
+        let odd: Vec<usize> = tree.degrees().iter().enumerate().filter(|&(_, degree)| degree % 2 == 1).map(|(city, _)| city).collect();
+        let matching = greedy_matching(&self.distance_matrix, &odd);
+        evaluated = evaluated.saturating_add(u32::try_from(odd.len() * odd.len().saturating_sub(1) / 2).unwrap_or(u32::MAX));
+
+        let edges: Vec<(usize, usize)> = tree.edges().chain(matching).collect();
+        let tour = shortcut(&euler_tour(self.n, &edges, root));
+        let distance = utils::calculate_tour_distance(&tour, &self.distance_matrix).unwrap();
+        Ok(SolveResult { tour, distance, steps: self.n as u32 - 1, evaluated, seed: self.seed })
+    }
+
+    /**
+     * Nearest, farthest or random insertion.
+     *
+     * @param start: The first city of the cycle
+     * @return: The tour and the number of distances and insertion costs looked at
+     */
+    fn selected_insertion(&mut self, start: usize) -> (Vec<u32>, u32) {
+        let n = self.n;
+        let mut cycle = Cycle::new(start, n);
+        let mut evaluated: u32 = 0;
+        // Cities not in the cycle yet and their distance to the nearest city of the cycle
+        let mut outside: Vec<usize> = (0..n).filter(|&city| city != start).collect();
+        let mut distance_to_cycle: Vec<f32> = (0..n).map(|city| self.distance_matrix.get(start, city)).collect();
+        evaluated = evaluated.saturating_add(outside.len() as u32);
+
+        while !outside.is_empty() {
+            let index = match self.method {
+                ConstructionMethod::NearestInsertion => (0..outside.len())
+                    .min_by(|&a, &b| distance_to_cycle[outside[a]].total_cmp(&distance_to_cycle[outside[b]]))
+                    .unwrap(),
+                ConstructionMethod::FarthestInsertion => (0..outside.len())
+                    .max_by(|&a, &b| distance_to_cycle[outside[a]].total_cmp(&distance_to_cycle[outside[b]]))
+                    .unwrap(),
+                _ => self.rng.gen_range(0..outside.len()),
+            };
+            let city = outside.swap_remove(index);
+
+            let (_, after) = cycle.cheapest_position(&self.distance_matrix, city);
+            evaluated = evaluated.saturating_add(cycle.len() as u32);
+            cycle.insert(after, city);
+
+            for &other in &outside {
+                distance_to_cycle[other] = distance_to_cycle[other].min(self.distance_matrix.get(city, other));
+            }
+            evaluated = evaluated.saturating_add(outside.len() as u32);
+        }
+        (cycle.order(start), evaluated)
+    }
+}
+
+/**
+ * The largest number of cities whose edges link_path lists in full, about
+ * 2M edges on a symmetric instance.
+ */
+const ALL_EDGES_MAX: usize = 2000;
+
+/**
+ * The number of nearest cities whose edges link_path lists for every city
+ * of a larger set.
+ */
+const NEAREST_EDGES: usize = 10;
+
+/**
+ * The edges between a set of cities with their weights, one direction per
+ * pair on a symmetric instance, both on an asymmetric one. Above
+ * ALL_EDGES_MAX cities, unless all is set, only the edges from every city
+ * to its NEAREST_EDGES nearest cities of the set.
+ *
+ * @param distance_matrix: The distance matrix of the TSP problem
+ * @param cities: The cities
+ * @param all: Whether to list all the edges whatever the number of cities
+ * @param weight: The weight of the edge from a city to another
+ * @return: The weights and the edges, sorted by (from, to)
+ */
+fn edges(distance_matrix: &DistanceMatrix, cities: &[usize], all: bool, weight: &impl Fn(usize, usize) -> f32) -> Vec<(f32, usize, usize)> {
+    let symmetric = distance_matrix.is_symmetric();
+    let mut pairs = Vec::new();
+    if all || cities.len() <= ALL_EDGES_MAX {
+        for (i, &from) in cities.iter().enumerate() {
+            for (j, &to) in cities.iter().enumerate() {
+                if i == j || (symmetric && j < i) { continue; }
+                pairs.push((from, to));
+            }
+        }
+    } else {
+        for &from in cities {
+            let mut nearest: Vec<(f32, usize)> = cities.iter().filter(|&&to| to != from).map(|&to| (distance_matrix.get(from, to), to)).collect();
+            nearest.select_nth_unstable_by(NEAREST_EDGES - 1, |a, b| a.0.total_cmp(&b.0));
+            nearest.truncate(NEAREST_EDGES);
+            pairs.extend(nearest.into_iter().map(|(_, to)| if symmetric { (from.min(to), from.max(to)) } else { (from, to) }));
+        }
+        pairs.sort_unstable();
+        pairs.dedup();
+    }
+    pairs.into_iter().map(|(from, to)| (weight(from, to), from, to)).collect()
+}
+
+/**
+ * Link a set of cities into a single path, taking the edges by increasing
+ * weight and skipping an edge if a city would get two successors or two
+ * predecessors, or if it closes a cycle. On a symmetric instance the
+ * direction of an edge does not matter, a city just gets at most two
+ * neighbours. The edges are linked in rounds over the cities still at the
+ * end of a fragment, all their edges once they are few enough or a round
+ * links nothing, otherwise only the edges to their nearest cities, so
+ * large instances never hold all the edges at once.
+ *
+ * @param distance_matrix: The distance matrix of the TSP problem
+ * @param cities: The cities
+ * @param weight: The weight of the edge from a city to another
+ * @return: The cities in path order and the number of weighed edges
+ */
+fn link_path(distance_matrix: &DistanceMatrix, cities: &[usize], weight: impl Fn(usize, usize) -> f32) -> (Vec<u32>, u32) {
+    let n = distance_matrix.len();
+    let symmetric = distance_matrix.is_symmetric();
+    let mut fragments = DisjointSet::new(n);
+    // Asymmetric paths run from links[city][1] to links[city][0]
+    let mut links = vec![[usize::MAX; 2]; n];
+    let mut linked = 0;
+    let mut evaluated: u32 = 0;
+
+    let mut ends = cities.to_vec();
+    let mut all = false;
+    while linked + 1 < cities.len() {
+        let mut edges = edges(distance_matrix, &ends, all, &weight);
+        evaluated = evaluated.saturating_add(edges.len() as u32);
+        edges.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let linked_before = linked;
+        for &(_, from, to) in &edges {
+            if linked + 1 >= cities.len() { break; }
+            let free = if symmetric {
+                links[from][1] == usize::MAX && links[to][1] == usize::MAX
+            } else {
+                links[from][0] == usize::MAX && links[to][1] == usize::MAX
+            };
+            if !free || !fragments.union(from, to) { continue; }
+            if symmetric {
+                let slot = if links[from][0] == usize::MAX { 0 } else { 1 };
+                links[from][slot] = to;
+                let slot = if links[to][0] == usize::MAX { 0 } else { 1 };
+                links[to][slot] = from;
+            } else {
+                links[from][0] = to;
+                links[to][1] = from;
+            }
+            linked += 1;
+        }
+        // The nearest cities of every end may all be ends of its own fragment
+        all = linked == linked_before;
+        ends.retain(|&city| links[city][1] == usize::MAX || (!symmetric && links[city][0] == usize::MAX));
+    }
+
+    // Walk the path from one of its ends, a city with a single neighbour or without a predecessor
+    let first = cities.iter().copied().find(|&city| links[city][1] == usize::MAX).unwrap_or(usize::MAX);
+    let mut path = Vec::with_capacity(cities.len());
+    let (mut previous, mut city) = (usize::MAX, first);
+    while city != usize::MAX {
+        path.push(city as u32);
+        let next = if symmetric && links[city][0] == previous { links[city][1] } else { links[city][0] };
+        (previous, city) = (city, next);
+    }
+    (path, evaluated)
+}
+
+/**
+ * Pair up an even number of cities, shortest pairs first. An edge weighs
+ * the shorter of its two directions.
+ *
+ * @param distance_matrix: The distance matrix of the TSP problem
+ * @param cities: The cities to pair up
+ * @return: The pairs
+ */
+fn greedy_matching(distance_matrix: &DistanceMatrix, cities: &[usize]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    for (i, &a) in cities.iter().enumerate() {
+        for &b in &cities[i + 1..] {
+            pairs.push((distance_matrix.get(a, b).min(distance_matrix.get(b, a)), a, b));
+        }
+    }
+    pairs.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    let mut matched = vec![false; distance_matrix.len()];
+    let mut matching = Vec::with_capacity(cities.len() / 2);
+    for (_, a, b) in pairs {
+        if matched[a] || matched[b] { continue; }
+        matched[a] = true;
+        matched[b] = true;
+        matching.push((a, b));
+    }
+    matching
+}
+
+/**
+ * Find a closed walk using every edge of a connected multigraph whose
+ * cities all have an even degree, with Hierholzer's algorithm.
+ *
+ * @param n: The number of cities
+ * @param edges: The edges, an edge listed twice is used twice
+ * @param start: The city the walk starts and ends at
+ * @return: The cities in walk order, start repeated at the end
+ */
+fn euler_tour(n: usize, edges: &[(usize, usize)], start: usize) -> Vec<usize> {
+    let mut incident = vec![Vec::new(); n];
+    for (edge, &(a, b)) in edges.iter().enumerate() {
+        incident[a].push(edge);
+        incident[b].push(edge);
+    }
+    let mut used = vec![false; edges.len()];
+    let mut walk = Vec::with_capacity(edges.len() + 1);
+    let mut stack = vec![start];
+    while let Some(&city) = stack.last() {
+        // Drop the used edges from the back of the list
+        while incident[city].last().is_some_and(|&edge| used[edge]) {
+            incident[city].pop();
+        }
+        match incident[city].pop() {
+            Some(edge) => {
+                used[edge] = true;
+                let (a, b) = edges[edge];
+                stack.push(if a == city { b } else { a });
+            },
+            None => {
+                walk.push(city);
+                stack.pop();
+            },
+        }
+    }
+    walk
+}
+
+/**
+ * Turn a walk into a tour by skipping the cities already visited.
+ */
+fn shortcut(walk: &[usize]) -> Vec<u32> {
+    let n = walk.iter().max().map_or(0, |&city| city + 1);
+    let mut visited = vec![false; n];
+    let mut tour = Vec::with_capacity(n);
+    for &city in walk {
+        if !visited[city] {
+            visited[city] = true;
+            tour.push(city as u32);
+        }
+    }
+    tour
+}
+
+/**
+ * DisjointSet struct
+ *
+ * Union-find over the cities, with path halving and union by size.
+ *
+ * @field parent: The parent of every city, the root of a set is its own parent
+ * @field size: The size of the set of every root
+ */
+struct DisjointSet {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl DisjointSet {
+    fn new(n: usize) -> DisjointSet {
+        DisjointSet { parent: (0..n).collect(), size: vec![1; n] }
+    }
+
+    fn find(&mut self, mut city: usize) -> usize {
+        while self.parent[city] != city {
+            self.parent[city] = self.parent[self.parent[city]];
+            city = self.parent[city];
+        }
+        city
+    }
+
+    /**
+     * Merge the sets of two cities.
+     *
+     * @return: Whether they were in different sets
+     */
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b { return false; }
+        if self.size[a] < self.size[b] { std::mem::swap(&mut a, &mut b); }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+        true
+    }
+}
+
+/**
+ * Cycle struct
+ *
+ * A growing cycle over a subset of the cities, as the successor of every city.
+ *
+ * @field next: The successor of every city of the cycle
+ * @field cities: The cities of the cycle
+ */
+struct Cycle {
+    next: Vec<usize>,
+    cities: Vec<usize>,
+}
+
+impl Cycle {
+    fn new(start: usize, n: usize) -> Cycle {
+        let mut next = vec![usize::MAX; n];
+        next[start] = start;
+        Cycle { next, cities: vec![start] }
+    }
+
+    fn len(&self) -> usize {
+        self.cities.len()
+    }
+
+    /**
+     * The increase of length of inserting a city between another one and its successor.
+     */
+    #[inline]
+    fn insertion_cost(&self, distance_matrix: &DistanceMatrix, after: usize, city: usize) -> f32 {
+        let before = self.next[after];
+        distance_matrix.get(after, city) + distance_matrix.get(city, before) - distance_matrix.get(after, before)
+    }
+
+    /**
+     * The cheapest place to insert a city.
+     *
+     * @return: The increase of length and the city to insert it after
+     */
+    fn cheapest_position(&self, distance_matrix: &DistanceMatrix, city: usize) -> (f32, usize) {
+        self.cities
+            .iter()
+            .map(|&after| (self.insertion_cost(distance_matrix, after, city), after))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .unwrap()
+    }
+
+    fn insert(&mut self, after: usize, city: usize) {
+        self.next[city] = self.next[after];
+        self.next[after] = city;
+        self.cities.push(city);
+    }
+
+    /**
+     * The cities in cycle order, from a city of the cycle on.
+     */
+    fn order(&self, start: usize) -> Vec<u32> {
+        let mut order = Vec::with_capacity(self.cities.len());
+        let mut city = start;
+        for _ in 0..self.cities.len() {
+            order.push(city as u32);
+            city = self.next[city];
+        }
+        order
+    }
+}
+
+/**
+ * Build a tour by repeatedly inserting the city, at the place, that
+ * lengthens the cycle the least. Every city outside keeps its cheapest
+ * place, only the cities whose place was the edge just broken are searched
+ * again in full.
+ *
+ * @param distance_matrix: The distance matrix of the TSP problem
+ * @param start: The first city of the cycle
+ * @return: The tour and the number of insertion costs looked at
+ */
+pub fn cheapest_insertion(distance_matrix: &DistanceMatrix, start: usize) -> (Vec<u32>, u32) {
+    let n = distance_matrix.len();
+    let mut cycle = Cycle::new(start, n);
+    let mut evaluated: u32 = 0;
+    let mut outside: Vec<usize> = (0..n).filter(|&city| city != start).collect();
+    // The cheapest place of every city outside the cycle
+    let mut best: Vec<(f32, usize)> = vec![(f32::INFINITY, start); n];
+    for &city in &outside {
+        best[city] = cycle.cheapest_position(distance_matrix, city);
+    }
+    evaluated = evaluated.saturating_add(outside.len() as u32);
+
+    while !outside.is_empty() {
+        let index = (0..outside.len()).min_by(|&a, &b| best[outside[a]].0.total_cmp(&best[outside[b]].0)).unwrap();
+        let city = outside.swap_remove(index);
+        let after = best[city].1;
+        cycle.insert(after, city);
+
+        // The edge (after, before) is now (after, city) and (city, before)
+        for &other in &outside {
+            if best[other].1 == after {
+                best[other] = cycle.cheapest_position(distance_matrix, other);
+                evaluated = evaluated.saturating_add(cycle.len() as u32);
+            } else {
+                for candidate in [after, city] {
+                    let cost = cycle.insertion_cost(distance_matrix, candidate, other);
+                    if cost < best[other].0 {
+                        best[other] = (cost, candidate);
+                    }
+                }
+                evaluated = evaluated.saturating_add(2);
+            }
+        }
+    }
+    (cycle.order(start), evaluated)
+}
+
+/**
+ * Build a tour by always going to one of the nearest unvisited cities.
+ *
+ * @param distance_matrix: The distance matrix of the TSP problem
+ * @param start: The first city
+ * @param rcl_size: The number of nearest cities the next one is drawn from, 1 takes the nearest
+ * @param rng: The random number generator
+ * @return: The tour and the number of distances looked at
+ */
+pub fn nearest_neighbour(distance_matrix: &DistanceMatrix, start: usize, rcl_size: usize, rng: &mut StdRng) -> (Vec<u32>, u32) {
+    let n = distance_matrix.len();
+    let mut visited = vec![false; n];
+    let mut tour = Vec::with_capacity(n);
+    let mut evaluated: u32 = 0;
+    // The nearest unvisited cities, sorted by distance
+    let mut nearest: Vec<(f32, usize)> = Vec::with_capacity(rcl_size + 1);
+
+    let mut current_city = start;
+    tour.push(current_city as u32);
+    visited[current_city] = true;
+    while tour.len() < n {
+        nearest.clear();
+        for (city, &is_visited) in visited.iter().enumerate() {
+            if is_visited { continue; }
+            evaluated = evaluated.saturating_add(1);
+            let distance = distance_matrix.get(current_city, city);
+            if nearest.len() < rcl_size || distance < nearest[nearest.len() - 1].0 {
+                // Ties keep the lower city first
+                let index = nearest.partition_point(|&(other, _)| other <= distance);
+                nearest.insert(index, (distance, city));
+                nearest.truncate(rcl_size);
+            }
+        }
+        let pick = if nearest.len() == 1 { 0 } else { rng.gen_range(0..nearest.len()) };
+        current_city = nearest[pick].1;
+        tour.push(current_city as u32);
+        visited[current_city] = true;
+    }
+    (tour, evaluated)
+}
+
+impl Solver for Construction {
+    fn name(&self) -> &'static str {
+        match self.method {
+            ConstructionMethod::NearestNeighbour => "nearest_neighbour",
+            ConstructionMethod::NearestNeighbourAllStarts => "nearest_neighbour_all_starts",
+            ConstructionMethod::Grasp => "grasp",
+            ConstructionMethod::NearestNeighbourKdTree => "nearest_neighbour_kd_tree",
+            ConstructionMethod::NearestInsertion => "nearest_insertion",
+            ConstructionMethod::FarthestInsertion => "farthest_insertion",
+            ConstructionMethod::CheapestInsertion => "cheapest_insertion",
+            ConstructionMethod::RandomInsertion => "random_insertion",
+            ConstructionMethod::GreedyEdge => "greedy_edge",
+            ConstructionMethod::Savings => "savings",
+            ConstructionMethod::MstDoubling => "mst_doubling",
+            ConstructionMethod::ChristofidesGreedyMatching => "christofides_greedy_matching",
+        }
+    }
+
+    fn metadata(&self) -> serde_json::Value {
+        match self.method {
+            ConstructionMethod::Grasp => json!({ "grasp_size": self.grasp_size }),
+            _ => json!({}),
+        }
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn solve_from(&mut self, _initial_tour: Option<Vec<u32>>) -> Result<SolveResult, &'static str> {
+        match self.method {
+            ConstructionMethod::NearestNeighbour => self.nearest_neighbour(),
+            ConstructionMethod::NearestNeighbourAllStarts => self.nearest_neighbour_all_starts(),
+            ConstructionMethod::Grasp => self.grasp(),
+            ConstructionMethod::NearestNeighbourKdTree => self.nearest_neighbour_kd_tree(),
+            ConstructionMethod::NearestInsertion
+            | ConstructionMethod::FarthestInsertion
+            | ConstructionMethod::CheapestInsertion
+            | ConstructionMethod::RandomInsertion => self.insertion(),
+            ConstructionMethod::GreedyEdge => self.greedy_edge(),
+            ConstructionMethod::Savings => self.savings(),
+            ConstructionMethod::MstDoubling => self.mst_doubling(),
+            ConstructionMethod::ChristofidesGreedyMatching => self.christofides_greedy_matching(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::distance::{Metric, Storage};
+
+    fn is_permutation(tour: &[u32], n: usize) -> bool {
+        let mut sorted = tour.to_vec();
+        sorted.sort_unstable();
+        sorted.iter().enumerate().all(|(i, &node)| node as usize == i) && sorted.len() == n
+    }
+
+    #[test]
+    fn greedy_edge_takes_the_shortest_edges() {
+        // Cities on a line: the greedy path links neighbours, only the closing edge is long
+        let coordinates: Vec<Coordinate> = [0.0, 3.0, 1.0, 6.0, 10.0].iter().map(|&x| Coordinate { x, y: 0.0, z: 0.0 }).collect();
+        let distance_matrix = DistanceMatrix::from_coordinates(&coordinates, Metric::Euc2d, Storage::Full);
+        let result = Construction::new(distance_matrix).with_method(ConstructionMethod::GreedyEdge).solve().unwrap();
+        assert_eq!(result.distance, 20.0);
+        assert_eq!(result.evaluated, 10);
+    }
+
+    #[test]
+    fn linking_large_instances_uses_the_nearest_edges() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let n = ALL_EDGES_MAX + 100;
+        let coordinates: Vec<Coordinate> = (0..n).map(|_| Coordinate { x: rng.gen_range(0.0..1000.0), y: rng.gen_range(0.0..1000.0), z: 0.0 }).collect();
+        let symmetric = DistanceMatrix::from_coordinates(&coordinates, Metric::Euc2d, Storage::Triangular);
+        let asymmetric = utils::random_distance_matrix(n, false, &mut rng);
+        for distance_matrix in [symmetric, asymmetric] {
+            let all_edges = if distance_matrix.is_symmetric() { n * (n - 1) / 2 } else { n * (n - 1) };
+            for method in [ConstructionMethod::GreedyEdge, ConstructionMethod::Savings] {
+                let result = Construction::new(distance_matrix.clone()).with_method(method).solve().unwrap();
+                assert!(is_permutation(&result.tour, n), "{:?} does not build a permutation", method);
+                assert!((result.evaluated as usize) < all_edges, "{:?} weighs {} of the {} edges", method, result.evaluated, all_edges);
+            }
+        }
+    }
+}
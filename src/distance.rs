@@ -0,0 +1,400 @@
+use crate::instance::{Coordinate, EdgeWeightFormat, EdgeWeightType};
+
+
+/**
+ * Metric enum
+ *
+ * The distance functions defined by TSPLIB for coordinate based instances,
+ * each with its exact rounding rule, plus the unrounded Euclidean distance.
+ *
+ * @variant Euc2d: Euclidean distance rounded to the nearest integer
+ * @variant Euc3d: 3D Euclidean distance rounded to the nearest integer
+ * @variant Max2d: Maximum of the rounded coordinate differences
+ * @variant Max3d: Maximum of the rounded 3D coordinate differences
+ * @variant Man2d: Manhattan distance rounded to the nearest integer
+ * @variant Man3d: 3D Manhattan distance rounded to the nearest integer
+ * @variant Ceil2d: Euclidean distance rounded up
+ * @variant Geo: Geographical distance on the idealised sphere, in kilometres
+ * @variant Att: Pseudo-Euclidean distance of the att48/att532 instances
+ * @variant RawEuclidean: Euclidean distance without any rounding (not part of TSPLIB)
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Euc2d,
+    Euc3d,
+    Max2d,
+    Max3d,
+    Man2d,
+    Man3d,
+    Ceil2d,
+    Geo,
+    Att,
+    RawEuclidean,
+}
+
+/**
+ * Round to the nearest integer the way TSPLIB does: (int) (x + 0.5).
+ *
+ * @param x: The value to round
+ * @return: The rounded value
+ */
+fn nint(x: f64) -> f64 {
+    (x + 0.5).trunc()
+}
+
+/**
+ * Convert a TSPLIB DDD.MM coordinate to radians.
+ *
+ * @param x: The coordinate in degrees and minutes
+ * @return: The coordinate in radians
+ */
+fn geo_radians(x: f64) -> f64 {
+    // TSPLIB uses a truncated value of pi
+    #[allow(clippy::approx_constant)]
+    const PI: f64 = 3.141592;
+    let degrees = x.trunc();
+    let minutes = x - degrees;
+    PI * (degrees + 5.0 * minutes / 3.0) / 180.0
+}
+
+impl Metric {
+    /**
+     * Metric matching a TSPLIB EDGE_WEIGHT_TYPE.
+     *
+     * @param edge_weight_type: The EDGE_WEIGHT_TYPE of the instance
+     * @return: The metric, or None if the weights are not computed from coordinates
+     */
+    pub fn from_edge_weight_type(edge_weight_type: EdgeWeightType) -> Option<Metric> {
+        match edge_weight_type {
+            EdgeWeightType::Euc2d => Some(Metric::Euc2d),
+            EdgeWeightType::Euc3d => Some(Metric::Euc3d),
+            EdgeWeightType::Max2d => Some(Metric::Max2d),
+            EdgeWeightType::Max3d => Some(Metric::Max3d),
+            EdgeWeightType::Man2d => Some(Metric::Man2d),
+            EdgeWeightType::Man3d => Some(Metric::Man3d),
+            EdgeWeightType::Ceil2d => Some(Metric::Ceil2d),
+            EdgeWeightType::Geo => Some(Metric::Geo),
+            EdgeWeightType::Att => Some(Metric::Att),
+            _ => None,
+        }
+    }
+
+    /**
+     * Whether the metric orders the pairs of nodes like the planar Euclidean
+     * distance between their x and y coordinates, the distance a k-d tree
+     * searches.
+     *
+     * @return: True for Euc2d, Ceil2d and RawEuclidean
+     */
+    pub fn is_planar_euclidean(&self) -> bool {
+        matches!(self, Metric::Euc2d | Metric::Ceil2d | Metric::RawEuclidean)
+    }
+
+    /**
+     * Calculate the distance between two coordinates.
+     *
+     * @param coord1: The first coordinate.
+     * @param coord2: The second coordinate.
+     * @return The distance between the two coordinates.
+     */
+    pub fn distance(&self, coord1: &Coordinate, coord2: &Coordinate) -> f32 {
+        let dx = coord1.x - coord2.x;
+        let dy = coord1.y - coord2.y;
+        let dz = coord1.z - coord2.z;
+        let distance = match self {
+            Metric::Euc2d => nint((dx * dx + dy * dy).sqrt()),
+            Metric::Euc3d => nint((dx * dx + dy * dy + dz * dz).sqrt()),
+            Metric::Max2d => nint(dx.abs()).max(nint(dy.abs())),
+            Metric::Max3d => nint(dx.abs()).max(nint(dy.abs())).max(nint(dz.abs())),
+            Metric::Man2d => nint(dx.abs() + dy.abs()),
+            Metric::Man3d => nint(dx.abs() + dy.abs() + dz.abs()),
+            Metric::Ceil2d => (dx * dx + dy * dy).sqrt().ceil(),
+            Metric::Geo => {
+                // Earth radius used by TSPLIB
+                const RRR: f64 = 6378.388;
+                let (latitude1, longitude1) = (geo_radians(coord1.x), geo_radians(coord1.y));
+                let (latitude2, longitude2) = (geo_radians(coord2.x), geo_radians(coord2.y));
+                let q1 = (longitude1 - longitude2).cos();
+                let q2 = (latitude1 - latitude2).cos();
+                let q3 = (latitude1 + latitude2).cos();
+                (RRR * (0.5 * ((1.0 + q1) * q2 - (1.0 - q1) * q3)).acos() + 1.0).trunc()
+            },
+            Metric::Att => {
+                let r = ((dx * dx + dy * dy) / 10.0).sqrt();
+                let t = nint(r);
+                if t < r { t + 1.0 } else { t }
+            },
+            Metric::RawEuclidean => (dx * dx + dy * dy).sqrt(),
+        };
+        distance as f32
+    }
+}
+
+/**
+ * Storage enum
+ *
+ * How a DistanceMatrix keeps its weights.
+ *
+ * @variant Full: All n * n weights in one row-major buffer, required for asymmetric instances
+ * @variant Triangular: Only the lower triangle, n * (n + 1) / 2 weights
+ * @variant OnTheFly: No weights at all, every distance is computed from the coordinates
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Storage {
+    Full,
+    Triangular,
+    OnTheFly,
+}
+
+impl Storage {
+    /**
+     * Most compact storage that still keeps lookups cheap for n nodes:
+     * up to 5000 nodes the full matrix takes at most 100MB, up to 20000
+     * the triangle takes at most 800MB.
+     *
+     * @param n: The number of nodes
+     * @return: The storage to use
+     */
+    pub fn for_size(n: usize) -> Storage {
+        if n <= 5_000 {
+            Storage::Full
+        } else if n <= 20_000 {
+            Storage::Triangular
+        } else {
+            Storage::OnTheFly
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Weights {
+    Full { weights: Vec<f32>, symmetric: bool },
+    Triangular(Vec<f32>),
+    OnTheFly { coordinates: Vec<Coordinate>, metric: Metric },
+}
+
+/**
+ * DistanceMatrix struct
+ *
+ * The distances between all pairs of nodes, stored in a flat buffer,
+ * a flat lower triangle or computed on demand from the coordinates.
+ *
+ * @field n: The number of nodes
+ * @field weights: The backing store
+ */
+#[derive(Debug, Clone)]
+pub struct DistanceMatrix {
+    n: usize,
+    weights: Weights,
+}
+
+/**
+ * Index of (i, j), j <= i, in a row-major lower triangle.
+ */
+#[inline]
+fn triangular_index(i: usize, j: usize) -> usize {
+    i * (i + 1) / 2 + j
+}
+
+impl DistanceMatrix {
+    /**
+     * Create a distance matrix from a set of coordinates.
+     *
+     * @param coordinates: The set of coordinates.
+     * @param metric: The distance function.
+     * @param storage: How to keep the weights.
+     * @return The distance matrix between the coordinates.
+     */
+    pub fn from_coordinates(coordinates: &[Coordinate], metric: Metric, storage: Storage) -> DistanceMatrix {
+        let n = coordinates.len();
+        let weights = match storage {
+            Storage::Full => {
+                let mut weights = vec![0.0; n * n];
+                for i in 0..n {
+                    // The diagonal stays 0, GEO would otherwise give 1 for i == j
+                    for j in i + 1..n {
+                        let dist = metric.distance(&coordinates[i], &coordinates[j]);
+                        weights[i * n + j] = dist;
+                        weights[j * n + i] = dist;
+                    }
+                }
+                Weights::Full { weights, symmetric: true }
+            },
+            Storage::Triangular => {
+                let mut weights = Vec::with_capacity(n * (n + 1) / 2);
+                for i in 0..n {
+                    for j in 0..i {
+                        weights.push(metric.distance(&coordinates[i], &coordinates[j]));
+                    }
+                    weights.push(0.0);
+                }
+                Weights::Triangular(weights)
+            },
+            Storage::OnTheFly => Weights::OnTheFly { coordinates: coordinates.to_vec(), metric },
+        };
+        DistanceMatrix { n, weights }
+    }
+
+    /**
+     * Create a distance matrix from the weights of an EDGE_WEIGHT_SECTION.
+     * Triangular formats are kept as a triangle, FULL_MATRIX as a full matrix.
+     *
+     * @param weights: The weights in the order they appear in the file
+     * @param format: The EDGE_WEIGHT_FORMAT of the section
+     * @param n: The number of nodes
+     * @return The distance matrix
+     */
+    pub fn from_explicit(weights: &[f32], format: EdgeWeightFormat, n: usize) -> DistanceMatrix {
+        if format == EdgeWeightFormat::FullMatrix {
+            let weights = weights.to_vec();
+            let symmetric = (0..n).all(|i| (0..i).all(|j| weights[i * n + j] == weights[j * n + i]));
+            return DistanceMatrix { n, weights: Weights::Full { weights, symmetric } };
+        }
+
+        // A triangle listed by columns is the opposite triangle listed by rows,
+        // which is the same for a symmetric matrix
+        let (upper, diagonal) = match format {
+            EdgeWeightFormat::UpperRow | EdgeWeightFormat::LowerCol => (true, false),
+            EdgeWeightFormat::LowerRow | EdgeWeightFormat::UpperCol => (false, false),
+            EdgeWeightFormat::UpperDiagRow | EdgeWeightFormat::LowerDiagCol => (true, true),
+            EdgeWeightFormat::LowerDiagRow | EdgeWeightFormat::UpperDiagCol => (false, true),
+            EdgeWeightFormat::FullMatrix | EdgeWeightFormat::Function => unreachable!(),
+        };
+        let mut triangle = vec![0.0; n * (n + 1) / 2];
+        let mut weights = weights.iter().copied();
+        for i in 0..n {
+            let columns = match (upper, diagonal) {
+                (true, false) => i + 1..n,
+                (true, true) => i..n,
+                (false, false) => 0..i,
+                (false, true) => 0..i + 1,
+            };
+            for j in columns {
+                let (row, column) = if j <= i { (i, j) } else { (j, i) };
+                triangle[triangular_index(row, column)] = weights.next().unwrap();
+            }
+        }
+        DistanceMatrix { n, weights: Weights::Triangular(triangle) }
+    }
+
+    /**
+     * Create a full distance matrix from nested rows.
+     *
+     * @param distance_matrix: The rows of the matrix
+     * @return The distance matrix
+     */
+    pub fn from_rows(distance_matrix: &[Vec<f32>]) -> DistanceMatrix {
+        let n = distance_matrix.len();
+        let weights: Vec<f32> = distance_matrix.iter().flatten().copied().collect();
+        let symmetric = (0..n).all(|i| (0..i).all(|j| distance_matrix[i][j] == distance_matrix[j][i]));
+        DistanceMatrix { n, weights: Weights::Full { weights, symmetric } }
+    }
+
+    /**
+     * The number of nodes.
+     */
+    pub fn len(&self) -> usize {
+        self.n
+    }
+
+    /**
+     * Whether the matrix has no nodes.
+     */
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    /**
+     * Whether get(i, j) == get(j, i) for all i, j.
+     */
+    pub fn is_symmetric(&self) -> bool {
+        match &self.weights {
+            Weights::Full { symmetric, .. } => *symmetric,
+            Weights::Triangular(_) | Weights::OnTheFly { .. } => true,
+        }
+    }
+
+    /**
+     * The storage backing the matrix.
+     */
+    pub fn storage(&self) -> Storage {
+        match &self.weights {
+            Weights::Full { .. } => Storage::Full,
+            Weights::Triangular(_) => Storage::Triangular,
+            Weights::OnTheFly { .. } => Storage::OnTheFly,
+        }
+    }
+
+    /**
+     * The distance from node i to node j.
+     *
+     * @param i: The first node
+     * @param j: The second node
+     * @return The distance
+     */
+    #[inline]
+    pub fn get(&self, i: usize, j: usize) -> f32 {
+        match &self.weights {
+            Weights::Full { weights, .. } => weights[i * self.n + j],
+            Weights::Triangular(weights) => {
+                if j <= i { weights[triangular_index(i, j)] } else { weights[triangular_index(j, i)] }
+            },
+            Weights::OnTheFly { coordinates, metric } => {
+                if i == j { 0.0 } else { metric.distance(&coordinates[i], &coordinates[j]) }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::Instance;
+
+    fn coordinate(x: f64, y: f64) -> Coordinate {
+        Coordinate { x, y, z: 0.0 }
+    }
+
+    #[test]
+    fn euc_2d_rounds_to_nearest() {
+        let origin = coordinate(0.0, 0.0);
+        assert_eq!(Metric::Euc2d.distance(&origin, &coordinate(1.0, 1.0)), 1.0);
+        assert_eq!(Metric::Euc2d.distance(&origin, &coordinate(1.0, 2.0)), 2.0);
+        // Halves round up
+        assert_eq!(Metric::Euc2d.distance(&origin, &coordinate(1.5, 2.0)), 3.0);
+    }
+
+    #[test]
+    fn ceil_2d_rounds_up() {
+        let origin = coordinate(0.0, 0.0);
+        assert_eq!(Metric::Ceil2d.distance(&origin, &coordinate(1.0, 1.0)), 2.0);
+        assert_eq!(Metric::Ceil2d.distance(&origin, &coordinate(3.0, 4.0)), 5.0);
+    }
+
+    #[test]
+    fn geo_matches_ulysses16() {
+        // Nodes 1 and 2 of ulysses16
+        assert_eq!(Metric::Geo.distance(&coordinate(38.24, 20.42), &coordinate(39.57, 26.15)), 509.0);
+    }
+
+    #[test]
+    fn att_matches_att48() {
+        // Nodes 1 and 2 of att48, the pseudo-Euclidean distance 1494.7 rounds up
+        assert_eq!(Metric::Att.distance(&coordinate(6734.0, 1453.0), &coordinate(2233.0, 10.0)), 1495.0);
+        // sqrt(10) rounds to 3 < sqrt(10), so it is bumped to 4
+        assert_eq!(Metric::Att.distance(&coordinate(0.0, 0.0), &coordinate(10.0, 0.0)), 4.0);
+    }
+
+    #[test]
+    fn berlin52_optimal_tour_length() {
+        let optimal_tour = [1, 49, 32, 45, 19, 41, 8, 9, 10, 43, 33, 51, 11, 52, 14, 13, 47, 26, 27, 28, 12, 25, 4, 6, 15, 5, 24, 48, 38, 37, 40, 39, 36, 35, 34, 44, 46, 16, 29, 50, 20, 23, 30, 2, 7, 42, 21, 17, 3, 18, 31, 22];
+        let instance = Instance::from_file("data/berlin52.txt").unwrap();
+        for storage in [Storage::Full, Storage::Triangular, Storage::OnTheFly] {
+            let distance_matrix = DistanceMatrix::from_coordinates(&instance.coordinates, Metric::Euc2d, storage);
+            let length: f32 = (0..optimal_tour.len())
+                .map(|i| distance_matrix.get(optimal_tour[i] - 1, optimal_tour[(i + 1) % optimal_tour.len()] - 1))
+                .sum();
+            assert_eq!(length, 7542.0, "{:?}", storage);
+        }
+    }
+}
@@ -0,0 +1,561 @@
+use serde_json::json;
+
+use crate::utils;
+use crate::distance::DistanceMatrix;
+use crate::lin_kernighan::LinKernighan;
+use crate::lower_bound::{HeldKarp, OneTree};
+use crate::solver::{SolveResult, Solver};
+use crate::termination::Termination;
+
+/**
+ * The largest number of cities the dynamic programming accepts, its table
+ * holds 2^(n - 1) * (n - 1) entries.
+ */
+pub const MAX_DYNAMIC_PROGRAMMING_CITIES: usize = 20;
+
+/**
+ * Subtracted from the weight of a required edge, so that every minimum
+ * 1-tree contains it.
+ */
+const REQUIRED_OFFSET: f64 = 1e9;
+
+/**
+ * The algorithm run by Exact as a Solver.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExactMethod {
+    DynamicProgramming,
+    BranchAndBound,
+}
+
+/**
+ * Exact struct
+ *
+ * Solvers that prove their tour optimal, meant as ground truth on small
+ * instances. The dynamic programming of Held and Karp takes O(2^n n²) time
+ * and handles asymmetric instances. The branch and bound starts from a
+ * Lin-Kernighan tour and branches on the edges of minimum 1-trees, bounded
+ * by subgradient optimisation of the node penalties. It needs a symmetric
+ * instance and stops at the time limit of the termination, in which case
+ * the best tour found is not proven optimal.
+ *
+ * @field distance_matrix: The distance matrix of the TSP problem
+ * @field n: The number of nodes
+ * @field method: The algorithm run by solve()
+ * @field node_iterations: The number of subgradient steps at every branch and bound node but the root
+ * @field termination: The stopping conditions of the branch and bound
+ * @field optimal: Whether the last run proved its tour optimal
+ * @field seed: The seed of the Lin-Kernighan giving the first tour of the branch and bound
+ */
+pub struct Exact {
+    pub distance_matrix: DistanceMatrix,
+    pub n: usize,
+    pub method: ExactMethod,
+    pub node_iterations: u32,
+    pub termination: Termination,
+    pub optimal: bool,
+    pub seed: u64,
+}
+
+impl Exact {
+    /**
+     * Create a new Exact instance
+     *
+     * @param distance_matrix: The distance matrix of the TSP problem
+     * @return: A new Exact instance
+     */
+    pub fn new(distance_matrix: DistanceMatrix) -> Exact {
+        let n = distance_matrix.len();
+        Exact {
+            distance_matrix,
+            n,
+            method: ExactMethod::DynamicProgramming,
+            node_iterations: 30,
+            termination: Termination::new(),
+            optimal: false,
+            seed: 0,
+        }
+    }
+
+    /**
+     * Set the algorithm run by solve()
+     *
+     * @param method: The algorithm
+     * @return: The Exact instance
+     */
+    pub fn with_method(mut self, method: ExactMethod) -> Exact {
+        self.method = method;
+        self
+    }
+
+    /**
+     * Solve the TSP problem with the dynamic programming of Held and Karp.
+     * The shortest path from city 0 through every subset of the other
+     * cities, ending at each of them, is built from the paths through the
+     * subsets one city smaller. Every extension of a path is an evaluation
+     * and every subset a step.
+     *
+     * @return: An optimal tour and its distance
+     */
+    pub fn dynamic_programming(&mut self) -> Result<SolveResult, &'static str> {
+        if self.n > MAX_DYNAMIC_PROGRAMMING_CITIES {
+            return Err("The dynamic programming solves instances of at most 20 cities");
+        }
+        if self.n <= 2 {
+            return Ok(self.trivial());
+        }
+        let dm = &self.distance_matrix;
+        // City j + 1 is bit j of a subset
+        let m = self.n - 1;
+        let subsets = 1usize << m;
+        // cost[subset * m + j]: the shortest path from city 0 through the subset, ending at city j + 1
+        let mut cost = vec![f32::INFINITY; subsets * m];
+        let mut evaluated: u32 = 0;
+        for j in 0..m {
+            cost[(1 << j) * m + j] = dm.get(0, j + 1);
+        }
+        for subset in 1..subsets {
+            for j in 0..m {
+                let path = cost[subset * m + j];
+                if subset & (1 << j) == 0 || path == f32::INFINITY { continue; }
+                for k in 0..m {
+                    if subset & (1 << k) != 0 { continue; }
+                    evaluated += 1;
+                    let extended = path + dm.get(j + 1, k + 1);
+                    let entry = &mut cost[(subset | (1 << k)) * m + k];
+                    if extended < *entry {
+                        *entry = extended;
+                    }
+                }
+            }
+        }
+
+        let all = subsets - 1;
+        let mut last = (0..m)
+            .min_by(|&a, &b| (cost[all * m + a] + dm.get(a + 1, 0)).total_cmp(&(cost[all * m + b] + dm.get(b + 1, 0))))
+            .unwrap();
+        // Walk the paths back, the predecessor is the city whose path extends to the same cost
+        let mut tour = vec![0; self.n];
+        let mut subset = all;
+        for position in (1..self.n).rev() {
+            tour[position] = (last + 1) as u32;
+            let rest = subset & !(1 << last);
+            if rest == 0 { break; }
+            last = (0..m)
+                .filter(|&i| rest & (1 << i) != 0)
+                .find(|&i| cost[rest * m + i] + dm.get(i + 1, last + 1) == cost[subset * m + last])
+                .unwrap();
+            subset = rest;
+        }
+
+        self.optimal = true;
+        let distance = utils::calculate_tour_distance(&tour, dm).unwrap();
+        Ok(SolveResult { tour, distance, steps: subsets as u32 - 1, evaluated, seed: self.seed })
+    }
+
+    /**
+     * Solve the TSP problem by branch and bound. A node of the search fixes
+     * some edges in or out of the tour, its bound is the best 1-tree bound
+     * honouring them. A node whose 1-tree is a tour needs no branching;
+     * otherwise a free 1-tree edge at a city of degree above 2 is forbidden
+     * in one child and required in the other. Every 1-tree is an
+     * evaluation and every node an iteration.
+     *
+     * @return: The best tour found and its distance
+     */
+    pub fn branch_and_bound(&mut self) -> Result<SolveResult, &'static str> {
+        if !self.distance_matrix.is_symmetric() {
+            return Err("The branch and bound needs a symmetric instance, the dynamic programming also solves asymmetric ones");
+        }
+        self.optimal = false;
+        if self.n <= 3 {
+            return Ok(self.trivial());
+        }
+        let mut progress = self.termination.start();
+
+        // The warm start shares the time and evaluation budget of the search
+        let mut warm_start_termination = Termination::new();
+        warm_start_termination.time_limit_ms = self.termination.time_limit_ms;
+        warm_start_termination.max_evaluations = self.termination.max_evaluations;
+        let mut lin_kernighan = LinKernighan::new(self.distance_matrix.clone());
+        lin_kernighan.set_termination(warm_start_termination);
+        lin_kernighan.set_seed(self.seed);
+        let warm_start = lin_kernighan.run(None)?;
+        progress.evaluated += warm_start.evaluated;
+        let mut best_tour = warm_start.tour;
+        let mut best_length = utils::tour_length(&best_tour, &self.distance_matrix);
+        progress.improve(best_length as f32);
+
+        // On integer distances, a bound within 1 of the best tour cannot lead to a shorter one
+        let integral = (0..self.n).all(|a| (0..self.n).all(|b| self.distance_matrix.get(a, b).fract() == 0.0));
+        let pruned = |bound: f64, best_length: f64| {
+            if integral { (bound - 1e-6).ceil() >= best_length } else { bound >= best_length - 1e-6 }
+        };
+        let node_ascent = HeldKarp { max_iterations: self.node_iterations, initial_step_size: 1.0, patience: 5 };
+
+        let mut stack = vec![(Constraints::new(self.n), vec![0.0; self.n])];
+        let mut exhausted = true;
+        while let Some((constraints, penalties)) = stack.pop() {
+            if progress.is_done() {
+                exhausted = false;
+                break;
+            }
+            let one_tree = |penalties: &[f64]| constraints.one_tree(&self.distance_matrix, penalties);
+            let ascent = if progress.iterations == 0 { HeldKarp::new() } else { node_ascent.clone() };
+            let bound = ascent.ascend(best_length, penalties, one_tree);
+            progress.evaluated += bound.iterations + 1;
+            progress.iterate(best_length as f32);
+            if pruned(bound.bound, best_length) { continue; }
+
+            let tree = one_tree(&bound.penalties);
+            if tree.is_tour() {
+                // The minimum 1-tree is a tour, nothing below this node is shorter
+                let tour = tree_tour(&tree);
+                let length = utils::tour_length(&tour, &self.distance_matrix);
+                if length < best_length {
+                    best_tour = tour;
+                    best_length = length;
+                    progress.improve(best_length as f32);
+                }
+                continue;
+            }
+
+            let (a, b) = constraints.branching_edge(&tree);
+            let mut required = constraints.clone();
+            if required.require(a, b) {
+                stack.push((required, bound.penalties.clone()));
+            }
+            let mut forbidden = constraints;
+            if forbidden.forbid(a, b) {
+                stack.push((forbidden, bound.penalties));
+            }
+        }
+
+        self.optimal = exhausted;
+        Ok(SolveResult { tour: best_tour, distance: best_length as f32, steps: progress.iterations, evaluated: progress.evaluated, seed: self.seed })
+    }
+
+    /**
+     * The only tour of an instance of at most 3 cities, up to the direction.
+     */
+    fn trivial(&mut self) -> SolveResult {
+        let mut tour: Vec<u32> = (0..self.n as u32).collect();
+        let mut distance = utils::calculate_tour_distance(&tour, &self.distance_matrix).unwrap();
+        if self.n == 3 {
+            let reversed = vec![0, 2, 1];
+            let reversed_distance = utils::calculate_tour_distance(&reversed, &self.distance_matrix).unwrap();
+            if reversed_distance < distance {
+                (tour, distance) = (reversed, reversed_distance);
+            }
+        }
+        self.optimal = true;
+        SolveResult { tour, distance, steps: 0, evaluated: 0, seed: self.seed }
+    }
+}
+
+/**
+ * The state of an edge in a branch and bound node.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EdgeState {
+    Free,
+    Required,
+    Forbidden,
+}
+
+/**
+ * Constraints struct
+ *
+ * The edges fixed in or out of the tour at a branch and bound node. The
+ * required edges always form paths: requiring an edge also forbids the
+ * other edges of a city with two required ones and the edge closing a path
+ * into a cycle too short.
+ *
+ * @field n: The number of cities
+ * @field state: The state of every edge, in both directions
+ * @field required: The other ends of the required edges of every city
+ */
+#[derive(Debug, Clone)]
+struct Constraints {
+    n: usize,
+    state: Vec<EdgeState>,
+    required: Vec<Vec<usize>>,
+}
+
+impl Constraints {
+    fn new(n: usize) -> Constraints {
+        Constraints { n, state: vec![EdgeState::Free; n * n], required: vec![Vec::new(); n] }
+    }
+
+    fn get(&self, a: usize, b: usize) -> EdgeState {
+        self.state[a * self.n + b]
+    }
+
+    fn set(&mut self, a: usize, b: usize, state: EdgeState) {
+        self.state[a * self.n + b] = state;
+        self.state[b * self.n + a] = state;
+    }
+
+    /**
+     * The minimum 1-tree honouring the constraints, its weight includes the penalties.
+     */
+    fn one_tree(&self, distance_matrix: &DistanceMatrix, penalties: &[f64]) -> OneTree {
+        let mut one_tree = OneTree::from_weights(self.n, |a, b| {
+            let weight = distance_matrix.get(a, b) as f64 + penalties[a] + penalties[b];
+            match self.get(a, b) {
+                EdgeState::Free => weight,
+                EdgeState::Required => weight - REQUIRED_OFFSET,
+                EdgeState::Forbidden => f64::INFINITY,
+            }
+        });
+        let required_edges = self.required.iter().map(Vec::len).sum::<usize>() / 2;
+        one_tree.weight += REQUIRED_OFFSET * required_edges as f64;
+        one_tree
+    }
+
+    /**
+     * A free edge of the 1-tree at one of the cities with the most edges.
+     */
+    fn branching_edge(&self, one_tree: &OneTree) -> (usize, usize) {
+        let city = (0..self.n).max_by_key(|&city| (one_tree.degrees[city], std::cmp::Reverse(city))).unwrap();
+        one_tree
+            .edges
+            .iter()
+            .copied()
+            .find(|&(a, b)| (a == city || b == city) && self.get(a, b) == EdgeState::Free)
+            .unwrap()
+    }
+
+    /**
+     * Forbid an edge.
+     *
+     * @return: Whether every city still has two edges left
+     */
+    fn forbid(&mut self, a: usize, b: usize) -> bool {
+        self.set(a, b, EdgeState::Forbidden);
+        self.has_two_edges(a) && self.has_two_edges(b)
+    }
+
+    /**
+     * Whether a city has at least two edges that are not forbidden.
+     */
+    fn has_two_edges(&self, city: usize) -> bool {
+        (0..self.n).filter(|&other| other != city && self.get(city, other) != EdgeState::Forbidden).nth(1).is_some()
+    }
+
+    /**
+     * Require an edge and forbid the edges it rules out.
+     *
+     * @return: Whether the required edges can still be part of a tour
+     */
+    fn require(&mut self, a: usize, b: usize) -> bool {
+        let (end, length) = self.path_end(a, usize::MAX);
+        let closes_cycle = end == b;
+        if closes_cycle && length < self.n {
+            return false;
+        }
+        self.set(a, b, EdgeState::Required);
+        self.required[a].push(b);
+        self.required[b].push(a);
+
+        for city in [a, b] {
+            if self.required[city].len() == 2 {
+                for other in 0..self.n {
+                    if other != city && self.get(city, other) == EdgeState::Free {
+                        self.set(city, other, EdgeState::Forbidden);
+                    }
+                }
+            }
+        }
+        if !closes_cycle {
+            // Joining the two ends of the new path would close a cycle too short
+            let (first, first_length) = self.path_end(a, b);
+            let (last, last_length) = self.path_end(b, a);
+            if first_length + last_length < self.n && self.get(first, last) == EdgeState::Free {
+                self.set(first, last, EdgeState::Forbidden);
+            }
+        }
+        (0..self.n).all(|city| self.has_two_edges(city))
+    }
+
+    /**
+     * Follow the required edges from a city, not going back to another one.
+     *
+     * @param city: The city to start from
+     * @param from: The city not to go to first, usize::MAX for none
+     * @return: The last city reached and the number of cities on the way, both ends included
+     */
+    fn path_end(&self, city: usize, from: usize) -> (usize, usize) {
+        let (mut previous, mut current) = (from, city);
+        let mut length = 1;
+        loop {
+            match self.required[current].iter().copied().find(|&next| next != previous) {
+                Some(next) if next != city => {
+                    (previous, current) = (current, next);
+                    length += 1;
+                },
+                _ => return (current, length),
+            }
+        }
+    }
+}
+
+/**
+ * The cities of a 1-tree that is a tour, in tour order from city 0.
+ */
+fn tree_tour(one_tree: &OneTree) -> Vec<u32> {
+    let n = one_tree.degrees.len();
+    let mut neighbours = vec![Vec::with_capacity(2); n];
+    for &(a, b) in &one_tree.edges {
+        neighbours[a].push(b);
+        neighbours[b].push(a);
+    }
+    let mut tour = Vec::with_capacity(n);
+    let (mut previous, mut city) = (usize::MAX, 0);
+    for _ in 0..n {
+        tour.push(city as u32);
+        let next = if neighbours[city][0] != previous { neighbours[city][0] } else { neighbours[city][1] };
+        (previous, city) = (city, next);
+    }
+    tour
+}
+
+impl Solver for Exact {
+    fn name(&self) -> &'static str {
+        match self.method {
+            ExactMethod::DynamicProgramming => "dynamic_programming",
+            ExactMethod::BranchAndBound => "branch_and_bound",
+        }
+    }
+
+    fn metadata(&self) -> serde_json::Value {
+        match self.method {
+            ExactMethod::DynamicProgramming => json!({}),
+            ExactMethod::BranchAndBound => json!({ "node_iterations": self.node_iterations, "termination": self.termination }),
+        }
+    }
+
+    fn set_termination(&mut self, termination: Termination) {
+        self.termination = termination;
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    fn solve_from(&mut self, _initial_tour: Option<Vec<u32>>) -> Result<SolveResult, &'static str> {
+        match self.method {
+            ExactMethod::DynamicProgramming => self.dynamic_programming(),
+            ExactMethod::BranchAndBound => self.branch_and_bound(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::instance::Instance;
+
+    /**
+     * The length of the shortest tour, by enumerating the orders of the cities after city 0.
+     */
+    fn brute_force(distance_matrix: &DistanceMatrix) -> f32 {
+        fn permute(tour: &mut Vec<u32>, k: usize, best: &mut f32, distance_matrix: &DistanceMatrix) {
+            if k == tour.len() {
+                *best = best.min(utils::calculate_tour_distance(tour, distance_matrix).unwrap());
+                return;
+            }
+            for i in k..tour.len() {
+                tour.swap(k, i);
+                permute(tour, k + 1, best, distance_matrix);
+                tour.swap(k, i);
+            }
+        }
+        let mut tour: Vec<u32> = (0..distance_matrix.len() as u32).collect();
+        let mut best = f32::INFINITY;
+        permute(&mut tour, 1, &mut best, distance_matrix);
+        best
+    }
+
+    fn check_tour(result: &SolveResult, distance_matrix: &DistanceMatrix) {
+        let mut sorted = result.tour.clone();
+        sorted.sort_unstable();
+        assert!(sorted.iter().enumerate().all(|(i, &node)| node as usize == i));
+        assert_eq!(result.distance, utils::calculate_tour_distance(&result.tour, distance_matrix).unwrap());
+    }
+
+    #[test]
+    fn dynamic_programming_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for symmetric in [true, false] {
+            for n in 4..=8 {
+                for _ in 0..5 {
+                    let distance_matrix = utils::random_distance_matrix(n, symmetric, &mut rng);
+                    let result = Exact::new(distance_matrix.clone()).dynamic_programming().unwrap();
+                    check_tour(&result, &distance_matrix);
+                    assert_eq!(result.distance, brute_force(&distance_matrix), "n = {}, symmetric = {}", n, symmetric);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn branch_and_bound_matches_dynamic_programming() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for n in 4..=12 {
+            for _ in 0..5 {
+                let distance_matrix = utils::random_distance_matrix(n, true, &mut rng);
+                let optimum = Exact::new(distance_matrix.clone()).dynamic_programming().unwrap().distance;
+                let mut solver = Exact::new(distance_matrix.clone()).with_method(ExactMethod::BranchAndBound);
+                let result = solver.branch_and_bound().unwrap();
+                check_tour(&result, &distance_matrix);
+                assert!(solver.optimal);
+                assert_eq!(result.distance, optimum, "n = {}", n);
+            }
+        }
+    }
+
+    #[test]
+    fn small_instances_are_trivial() {
+        for n in 0..4 {
+            let distance_matrix = utils::random_distance_matrix(n, true, &mut StdRng::seed_from_u64(2));
+            for method in [ExactMethod::DynamicProgramming, ExactMethod::BranchAndBound] {
+                let result = Exact::new(distance_matrix.clone()).with_method(method).solve().unwrap();
+                assert_eq!(result.tour.len(), n);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_solve() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let asymmetric = utils::random_distance_matrix(6, false, &mut rng);
+        assert!(Exact::new(asymmetric).branch_and_bound().is_err());
+        let large = utils::random_distance_matrix(MAX_DYNAMIC_PROGRAMMING_CITIES + 1, true, &mut rng);
+        assert!(Exact::new(large).dynamic_programming().is_err());
+    }
+
+    #[test]
+    fn branch_and_bound_solves_berlin52() {
+        let instance = Instance::from_file("data/berlin52.txt").unwrap();
+        let mut solver = Exact::new(instance.distance_matrix.clone()).with_method(ExactMethod::BranchAndBound);
+        let result = solver.branch_and_bound().unwrap();
+        assert!(solver.optimal);
+        assert_eq!(result.distance, 7542.0);
+    }
+
+    #[test]
+    fn warm_start_shares_the_budget() {
+        let instance = Instance::from_file("data/kroA100.txt").unwrap();
+        let mut solver = Exact::new(instance.distance_matrix.clone()).with_method(ExactMethod::BranchAndBound);
+        solver.set_termination(Termination::new().with_max_evaluations(500));
+        let result = solver.branch_and_bound().unwrap();
+        // Unbounded, the Lin-Kernighan warm start alone evaluates far more moves;
+        // bounded, it may only finish the step it is in
+        assert!(result.evaluated < 1000, "{} evaluations", result.evaluated);
+        assert!(!solver.optimal);
+    }
+}
@@ -0,0 +1,547 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead};
+
+use crate::distance::{DistanceMatrix, Metric, Storage};
+
+
+/**
+ * Errors raised while reading a TSPLIB instance.
+ *
+ * @variant Io: The file could not be opened or read
+ * @variant MissingField: A mandatory header field is absent
+ * @variant InvalidValue: A header field has a value that could not be parsed
+ * @variant Unsupported: A header field has a valid TSPLIB value that is not supported
+ * @variant MalformedLine: A data line could not be parsed
+ * @variant DimensionMismatch: The number of nodes read differs from DIMENSION
+ * @variant WeightCountMismatch: The number of edge weights read differs from what
+ * EDGE_WEIGHT_FORMAT and DIMENSION require
+ */
+#[derive(Debug)]
+pub enum InstanceError {
+    Io(io::Error),
+    MissingField(&'static str),
+    InvalidValue { field: String, value: String },
+    Unsupported { field: String, value: String },
+    MalformedLine { line: usize, content: String },
+    DimensionMismatch { expected: usize, found: usize },
+    WeightCountMismatch { expected: usize, found: usize },
+}
+
+impl fmt::Display for InstanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstanceError::Io(err) => write!(f, "I/O error: {}", err),
+            InstanceError::MissingField(field) => write!(f, "missing mandatory field {}", field),
+            InstanceError::InvalidValue { field, value } => write!(f, "invalid value {:?} for field {}", value, field),
+            InstanceError::Unsupported { field, value } => write!(f, "unsupported value {:?} for field {}", value, field),
+            InstanceError::MalformedLine { line, content } => write!(f, "malformed line {}: {:?}", line, content),
+            InstanceError::DimensionMismatch { expected, found } => write!(f, "expected {} nodes (DIMENSION), found {}", expected, found),
+            InstanceError::WeightCountMismatch { expected, found } => write!(f, "expected {} edge weights, found {}", expected, found),
+        }
+    }
+}
+
+impl std::error::Error for InstanceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InstanceError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for InstanceError {
+    fn from(err: io::Error) -> InstanceError {
+        InstanceError::Io(err)
+    }
+}
+
+
+/**
+ * TSPLIB problem TYPE.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProblemType {
+    Tsp,
+    Atsp,
+}
+
+impl ProblemType {
+    fn parse(value: &str) -> Result<ProblemType, InstanceError> {
+        match value {
+            "TSP" => Ok(ProblemType::Tsp),
+            "ATSP" => Ok(ProblemType::Atsp),
+            "SOP" | "HCP" | "CVRP" | "TOUR" => Err(InstanceError::Unsupported { field: "TYPE".to_string(), value: value.to_string() }),
+            _ => Err(InstanceError::InvalidValue { field: "TYPE".to_string(), value: value.to_string() }),
+        }
+    }
+}
+
+/**
+ * TSPLIB EDGE_WEIGHT_TYPE.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeWeightType {
+    Explicit,
+    Euc2d,
+    Euc3d,
+    Max2d,
+    Max3d,
+    Man2d,
+    Man3d,
+    Ceil2d,
+    Geo,
+    Att,
+    Xray1,
+    Xray2,
+    Special,
+}
+
+impl EdgeWeightType {
+    fn parse(value: &str) -> Result<EdgeWeightType, InstanceError> {
+        match value {
+            "EXPLICIT" => Ok(EdgeWeightType::Explicit),
+            "EUC_2D" => Ok(EdgeWeightType::Euc2d),
+            "EUC_3D" => Ok(EdgeWeightType::Euc3d),
+            "MAX_2D" => Ok(EdgeWeightType::Max2d),
+            "MAX_3D" => Ok(EdgeWeightType::Max3d),
+            "MAN_2D" => Ok(EdgeWeightType::Man2d),
+            "MAN_3D" => Ok(EdgeWeightType::Man3d),
+            "CEIL_2D" => Ok(EdgeWeightType::Ceil2d),
+            "GEO" => Ok(EdgeWeightType::Geo),
+            "ATT" => Ok(EdgeWeightType::Att),
+            "XRAY1" => Ok(EdgeWeightType::Xray1),
+            "XRAY2" => Ok(EdgeWeightType::Xray2),
+            "SPECIAL" => Ok(EdgeWeightType::Special),
+            _ => Err(InstanceError::InvalidValue { field: "EDGE_WEIGHT_TYPE".to_string(), value: value.to_string() }),
+        }
+    }
+}
+
+/**
+ * TSPLIB EDGE_WEIGHT_FORMAT, the layout of an EDGE_WEIGHT_SECTION.
+ *
+ * ROW formats list the matrix row by row, COL formats column by column.
+ * UPPER/LOWER select the triangle, DIAG whether the diagonal is included.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeWeightFormat {
+    Function,
+    FullMatrix,
+    UpperRow,
+    LowerRow,
+    UpperDiagRow,
+    LowerDiagRow,
+    UpperCol,
+    LowerCol,
+    UpperDiagCol,
+    LowerDiagCol,
+}
+
+impl EdgeWeightFormat {
+    fn parse(value: &str) -> Result<EdgeWeightFormat, InstanceError> {
+        match value {
+            "FUNCTION" => Ok(EdgeWeightFormat::Function),
+            "FULL_MATRIX" => Ok(EdgeWeightFormat::FullMatrix),
+            "UPPER_ROW" => Ok(EdgeWeightFormat::UpperRow),
+            "LOWER_ROW" => Ok(EdgeWeightFormat::LowerRow),
+            "UPPER_DIAG_ROW" => Ok(EdgeWeightFormat::UpperDiagRow),
+            "LOWER_DIAG_ROW" => Ok(EdgeWeightFormat::LowerDiagRow),
+            "UPPER_COL" => Ok(EdgeWeightFormat::UpperCol),
+            "LOWER_COL" => Ok(EdgeWeightFormat::LowerCol),
+            "UPPER_DIAG_COL" => Ok(EdgeWeightFormat::UpperDiagCol),
+            "LOWER_DIAG_COL" => Ok(EdgeWeightFormat::LowerDiagCol),
+            _ => Err(InstanceError::InvalidValue { field: "EDGE_WEIGHT_FORMAT".to_string(), value: value.to_string() }),
+        }
+    }
+
+    /**
+     * Number of weights an EDGE_WEIGHT_SECTION holds in this format.
+     *
+     * @param n: The number of nodes
+     * @return: The number of weights, or None for FUNCTION which has no section
+     */
+    pub fn n_weights(&self, n: usize) -> Option<usize> {
+        match self {
+            EdgeWeightFormat::Function => None,
+            EdgeWeightFormat::FullMatrix => Some(n * n),
+            EdgeWeightFormat::UpperRow | EdgeWeightFormat::LowerRow
+            | EdgeWeightFormat::UpperCol | EdgeWeightFormat::LowerCol => Some(n * n.saturating_sub(1) / 2),
+            EdgeWeightFormat::UpperDiagRow | EdgeWeightFormat::LowerDiagRow
+            | EdgeWeightFormat::UpperDiagCol | EdgeWeightFormat::LowerDiagCol => Some(n * (n + 1) / 2),
+        }
+    }
+}
+
+/**
+ * The part of the file being read.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Specification,
+    NodeCoordinates,
+    EdgeWeights,
+    Skipped,
+}
+
+
+/**
+ * A struct to represent the coordinates of a node.
+ *
+ * @field x: The x-coordinate of the point.
+ * @field y: The y-coordinate of the point.
+ * @field z: The z-coordinate of the point (0 for 2D instances).
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coordinate {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+/**
+ * Instance struct
+ *
+ * A TSPLIB instance: its specification part and the distance matrix
+ * built from its data part.
+ *
+ * @field name: The NAME of the instance
+ * @field problem_type: The TYPE of the instance
+ * @field comment: The COMMENT lines joined with a newline
+ * @field dimension: The number of nodes
+ * @field edge_weight_type: How the edge weights are given
+ * @field edge_weight_format: The layout of explicit edge weights, if given
+ * @field coordinates: The node coordinates (empty if none are given)
+ * @field metric: The distance function applied to the coordinates
+ * @field symmetric: Whether d(i, j) == d(j, i) for all i, j, false for every ATSP
+ * @field distance_matrix: The distance matrix of the instance
+ */
+#[derive(Debug, Clone)]
+pub struct Instance {
+    pub name: String,
+    pub problem_type: ProblemType,
+    pub comment: String,
+    pub dimension: usize,
+    pub edge_weight_type: EdgeWeightType,
+    pub edge_weight_format: Option<EdgeWeightFormat>,
+    pub coordinates: Vec<Coordinate>,
+    pub metric: Option<Metric>,
+    pub symmetric: bool,
+    pub distance_matrix: DistanceMatrix,
+}
+
+/**
+ * Split a specification line into its keyword and value.
+ * Both "KEY: VALUE" and "KEY : VALUE" are accepted.
+ *
+ * @param line: The trimmed line
+ * @return: The keyword and the (possibly empty) value
+ */
+fn split_keyword(line: &str) -> (&str, &str) {
+    match line.split_once(':') {
+        Some((key, value)) => (key.trim(), value.trim()),
+        None => (line.trim(), ""),
+    }
+}
+
+/**
+ * Parse a single NODE_COORD_SECTION line.
+ *
+ * @param parts: The whitespace separated tokens of the line
+ * @return: The 1-based node id and its coordinate, or None if malformed
+ */
+fn parse_coordinate(parts: &[&str]) -> Option<(usize, Coordinate)> {
+    if parts.len() != 3 && parts.len() != 4 {
+        return None;
+    }
+    let id = parts[0].parse::<usize>().ok()?;
+    let x = parts[1].parse::<f64>().ok()?;
+    let y = parts[2].parse::<f64>().ok()?;
+    let z = match parts.get(3) {
+        Some(z) => z.parse::<f64>().ok()?,
+        None => 0.0,
+    };
+    Some((id, Coordinate { x, y, z }))
+}
+
+impl Instance {
+    /**
+     * Parse a TSPLIB instance from a reader.
+     *
+     * @param reader: The reader with the instance contents
+     * @return: The parsed instance
+     */
+    pub fn parse<R: BufRead>(reader: R) -> Result<Instance, InstanceError> {
+        let mut name = None;
+        let mut problem_type = None;
+        let mut comment: Vec<String> = Vec::new();
+        let mut dimension = None;
+        let mut edge_weight_type = None;
+        let mut edge_weight_format = None;
+
+        let mut coordinates: Vec<Option<Coordinate>> = Vec::new();
+        let mut n_coordinates = 0;
+        let mut weights: Vec<f32> = Vec::new();
+        let mut section = Section::Specification;
+        for (line_index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line == "EOF" {
+                break;
+            }
+
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            // Any line not starting with a number ends a data section
+            if section != Section::Specification && parts[0].parse::<f64>().is_ok() {
+                let malformed = || InstanceError::MalformedLine { line: line_index + 1, content: line.to_string() };
+                match section {
+                    Section::NodeCoordinates => {
+                        let (id, coordinate) = parse_coordinate(&parts).ok_or_else(malformed)?;
+                        if id == 0 || id > coordinates.len() || coordinates[id - 1].is_some() {
+                            return Err(malformed());
+                        }
+                        coordinates[id - 1] = Some(coordinate);
+                        n_coordinates += 1;
+                    },
+                    Section::EdgeWeights => {
+                        // Weights are a plain stream of numbers, line breaks carry no meaning
+                        for part in &parts {
+                            weights.push(part.parse::<f32>().map_err(|_| malformed())?);
+                        }
+                    },
+                    Section::Skipped | Section::Specification => {},
+                }
+                continue;
+            }
+            section = Section::Specification;
+
+            let (key, value) = split_keyword(line);
+            match key {
+                "NAME" => name = Some(value.to_string()),
+                "TYPE" => problem_type = Some(ProblemType::parse(value)?),
+                "COMMENT" => comment.push(value.to_string()),
+                "DIMENSION" => {
+                    let parsed = value.parse::<usize>().ok().filter(|&dimension| dimension >= 1);
+                    dimension = Some(parsed.ok_or_else(|| InstanceError::InvalidValue { field: key.to_string(), value: value.to_string() })?);
+                },
+                "EDGE_WEIGHT_TYPE" => edge_weight_type = Some(EdgeWeightType::parse(value)?),
+                "EDGE_WEIGHT_FORMAT" => edge_weight_format = Some(EdgeWeightFormat::parse(value)?),
+                // Only relevant for display purposes, coordinates are parsed as given
+                "NODE_COORD_TYPE" | "DISPLAY_DATA_TYPE" => {},
+                "NODE_COORD_SECTION" => {
+                    let dimension = dimension.ok_or(InstanceError::MissingField("DIMENSION"))?;
+                    coordinates = vec![None; dimension];
+                    section = Section::NodeCoordinates;
+                },
+                "EDGE_WEIGHT_SECTION" => section = Section::EdgeWeights,
+                // Neither of them changes the distances
+                "DISPLAY_DATA_SECTION" | "FIXED_EDGES_SECTION" => section = Section::Skipped,
+                _ => return Err(InstanceError::Unsupported { field: key.to_string(), value: value.to_string() }),
+            }
+        }
+
+        let name = name.ok_or(InstanceError::MissingField("NAME"))?;
+        let problem_type = problem_type.ok_or(InstanceError::MissingField("TYPE"))?;
+        let dimension = dimension.ok_or(InstanceError::MissingField("DIMENSION"))?;
+        let edge_weight_type = edge_weight_type.ok_or(InstanceError::MissingField("EDGE_WEIGHT_TYPE"))?;
+
+        if n_coordinates != 0 && n_coordinates != dimension {
+            return Err(InstanceError::DimensionMismatch { expected: dimension, found: n_coordinates });
+        }
+        let coordinates: Vec<Coordinate> = coordinates.into_iter().flatten().collect();
+
+        let (metric, distance_matrix) = if edge_weight_type == EdgeWeightType::Explicit {
+            let edge_weight_format = edge_weight_format.ok_or(InstanceError::MissingField("EDGE_WEIGHT_FORMAT"))?;
+            let expected = edge_weight_format.n_weights(dimension).ok_or_else(|| InstanceError::InvalidValue {
+                field: "EDGE_WEIGHT_FORMAT".to_string(),
+                value: format!("{:?}", edge_weight_format),
+            })?;
+            if weights.len() != expected {
+                return Err(InstanceError::WeightCountMismatch { expected, found: weights.len() });
+            }
+            (None, DistanceMatrix::from_explicit(&weights, edge_weight_format, dimension))
+        } else {
+            let metric = Metric::from_edge_weight_type(edge_weight_type).ok_or_else(|| InstanceError::Unsupported {
+                field: "EDGE_WEIGHT_TYPE".to_string(),
+                value: format!("{:?}", edge_weight_type),
+            })?;
+            if n_coordinates == 0 {
+                return Err(InstanceError::MissingField("NODE_COORD_SECTION"));
+            }
+            (Some(metric), DistanceMatrix::from_coordinates(&coordinates, metric, Storage::for_size(dimension)))
+        };
+
+        let symmetric = problem_type == ProblemType::Tsp && distance_matrix.is_symmetric();
+
+        Ok(Instance {
+            name,
+            problem_type,
+            comment: comment.join("\n"),
+            dimension,
+            edge_weight_type,
+            edge_weight_format,
+            coordinates,
+            metric,
+            symmetric,
+            distance_matrix,
+        })
+    }
+
+    /**
+     * Recompute the distance matrix with a different distance function,
+     * e.g. Metric::RawEuclidean to ignore the TSPLIB rounding rule.
+     *
+     * @param metric: The distance function
+     * @return: The instance with the new distance matrix, or an error if it has no coordinates
+     */
+    pub fn with_metric(mut self, metric: Metric) -> Result<Instance, InstanceError> {
+        if self.coordinates.len() != self.dimension {
+            return Err(InstanceError::MissingField("NODE_COORD_SECTION"));
+        }
+        self.distance_matrix = DistanceMatrix::from_coordinates(&self.coordinates, metric, self.distance_matrix.storage());
+        self.metric = Some(metric);
+        Ok(self)
+    }
+
+    /**
+     * Rebuild the distance matrix of a coordinate based instance with a different storage,
+     * e.g. Storage::OnTheFly to bound memory or Storage::Full for the fastest lookups.
+     *
+     * @param storage: How to keep the weights
+     * @return: The instance with the new distance matrix, or an error if it has no coordinates
+     */
+    pub fn with_storage(mut self, storage: Storage) -> Result<Instance, InstanceError> {
+        let metric = self.metric.ok_or(InstanceError::MissingField("NODE_COORD_SECTION"))?;
+        self.distance_matrix = DistanceMatrix::from_coordinates(&self.coordinates, metric, storage);
+        Ok(self)
+    }
+
+    /**
+     * Read a TSPLIB instance from a file.
+     *
+     * @param file_path: The path to the file
+     * @return: The parsed instance
+     */
+    pub fn from_file(file_path: &str) -> Result<Instance, InstanceError> {
+        let file = File::open(file_path)?;
+        Instance::parse(io::BufReader::new(file))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str) -> Result<Instance, InstanceError> {
+        Instance::parse(contents.as_bytes())
+    }
+
+    /**
+     * The weight between two different nodes of the explicit test instance, unique per pair.
+     */
+    fn weight(i: usize, j: usize) -> usize {
+        if i == j { 0 } else { 10 * i.min(j) + i.max(j) }
+    }
+
+    fn explicit(format: &str, weights: &[usize]) -> String {
+        let weights: Vec<String> = weights.iter().map(usize::to_string).collect();
+        format!("NAME: test\nTYPE: TSP\nDIMENSION: 4\nEDGE_WEIGHT_TYPE: EXPLICIT\nEDGE_WEIGHT_FORMAT: {}\nEDGE_WEIGHT_SECTION\n{}\nEOF\n", format, weights.join(" "))
+    }
+
+    #[test]
+    fn parses_coordinates() {
+        let instance = parse("NAME : test\nTYPE : TSP\nCOMMENT : three nodes\nDIMENSION : 3\nEDGE_WEIGHT_TYPE : EUC_2D\nNODE_COORD_SECTION\n1 0 0\n2 3 4\n3 0 4\nEOF\n").unwrap();
+        assert_eq!(instance.name, "test");
+        assert_eq!(instance.dimension, 3);
+        assert!(instance.symmetric);
+        assert_eq!(instance.distance_matrix.get(0, 1), 5.0);
+        assert_eq!(instance.distance_matrix.get(2, 1), 3.0);
+    }
+
+    #[test]
+    fn rejects_coordinate_count_mismatch() {
+        let result = parse("NAME: test\nTYPE: TSP\nDIMENSION: 3\nEDGE_WEIGHT_TYPE: EUC_2D\nNODE_COORD_SECTION\n1 0 0\n2 3 4\nEOF\n");
+        assert!(matches!(result, Err(InstanceError::DimensionMismatch { expected: 3, found: 2 })));
+    }
+
+    #[test]
+    fn rejects_unknown_keyword() {
+        let result = parse("NAME: test\nTYPE: TSP\nCAPACITY: 10\nDIMENSION: 3\nEDGE_WEIGHT_TYPE: EUC_2D\nEOF\n");
+        assert!(matches!(result, Err(InstanceError::Unsupported { field, .. }) if field == "CAPACITY"));
+    }
+
+    #[test]
+    fn rejects_missing_section() {
+        let result = parse("NAME: test\nTYPE: TSP\nDIMENSION: 3\nEDGE_WEIGHT_TYPE: EUC_2D\nEOF\n");
+        assert!(matches!(result, Err(InstanceError::MissingField("NODE_COORD_SECTION"))));
+    }
+
+    #[test]
+    fn rejects_empty_dimension() {
+        let result = parse("NAME: test\nTYPE: TSP\nDIMENSION: 0\nEDGE_WEIGHT_TYPE: EXPLICIT\nEDGE_WEIGHT_FORMAT: UPPER_ROW\nEDGE_WEIGHT_SECTION\nEOF\n");
+        assert!(matches!(result, Err(InstanceError::InvalidValue { field, .. }) if field == "DIMENSION"));
+    }
+
+    #[test]
+    fn rejects_weight_count_mismatch() {
+        let result = parse(&explicit("UPPER_ROW", &[1, 2, 3, 12, 13]));
+        assert!(matches!(result, Err(InstanceError::WeightCountMismatch { expected: 6, found: 5 })));
+    }
+
+    #[test]
+    fn parses_every_edge_weight_format() {
+        let n = 4;
+        let upper = |diagonal: bool| -> Vec<(usize, usize)> {
+            (0..n).flat_map(|i| (0..n).map(move |j| (i, j))).filter(|&(i, j)| j > i || (diagonal && j == i)).collect()
+        };
+        let lower = |diagonal: bool| -> Vec<(usize, usize)> {
+            (0..n).flat_map(|i| (0..n).map(move |j| (i, j))).filter(|&(i, j)| j < i || (diagonal && j == i)).collect()
+        };
+        // Listing by columns swaps the roles of the row and the column
+        let transpose = |pairs: Vec<(usize, usize)>| -> Vec<(usize, usize)> { pairs.into_iter().map(|(i, j)| (j, i)).collect() };
+        let formats = [
+            ("UPPER_ROW", upper(false)),
+            ("LOWER_ROW", lower(false)),
+            ("UPPER_DIAG_ROW", upper(true)),
+            ("LOWER_DIAG_ROW", lower(true)),
+            ("UPPER_COL", transpose(lower(false))),
+            ("LOWER_COL", transpose(upper(false))),
+            ("UPPER_DIAG_COL", transpose(lower(true))),
+            ("LOWER_DIAG_COL", transpose(upper(true))),
+        ];
+        for (format, pairs) in formats {
+            let weights: Vec<usize> = pairs.iter().map(|&(i, j)| weight(i, j)).collect();
+            let instance = parse(&explicit(format, &weights)).unwrap();
+            assert_eq!(instance.edge_weight_format, Some(EdgeWeightFormat::parse(format).unwrap()));
+            for i in 0..n {
+                for j in 0..n {
+                    assert_eq!(instance.distance_matrix.get(i, j), weight(i, j) as f32, "{} ({}, {})", format, i, j);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parses_asymmetric_full_matrix() {
+        let weights = [0, 1, 2, 3, 4, 0, 5, 6, 7, 8, 0, 9, 10, 11, 12, 0];
+        let contents = explicit("FULL_MATRIX", &weights).replace("TYPE: TSP", "TYPE: ATSP");
+        let instance = parse(&contents).unwrap();
+        assert!(!instance.symmetric);
+        for i in 0..4 {
+            for j in 0..4 {
+                assert_eq!(instance.distance_matrix.get(i, j), weights[i * 4 + j] as f32);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_function_format_with_explicit_weights() {
+        let result = parse(&explicit("FUNCTION", &[]));
+        assert!(matches!(result, Err(InstanceError::InvalidValue { field, .. }) if field == "EDGE_WEIGHT_FORMAT"));
+    }
+}
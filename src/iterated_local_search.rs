@@ -0,0 +1,291 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+
+use crate::utils;
+use crate::candidate_list::CandidateList;
+use crate::distance::DistanceMatrix;
+use crate::local_search::{LocalSearch, LocalSearchMethod};
+use crate::solver::{SolveResult, Solver};
+use crate::termination::{Progress, Termination};
+
+/**
+ * Perturbation enum
+ *
+ * The kick applied to the current local optimum.
+ *
+ * @variant DoubleBridge: Cut the tour into A B C D at three random points and reconnect it as A C B D
+ * @variant RandomSegment: A double bridge with its cut points in a random segment of at most segment_length cities
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Perturbation {
+    DoubleBridge,
+    RandomSegment,
+}
+
+/**
+ * Acceptance enum
+ *
+ * Which local optimum the next kick starts from.
+ *
+ * @variant Better: The new local optimum if it is shorter than the current one
+ * @variant RandomWalk: Always the new local optimum
+ * @variant Restart: As Better, but a local optimum of a random tour after restart_after kicks without improving the best tour
+ * @variant Lsmc: The new local optimum if it is shorter, otherwise with the probability exp(-delta / temperature) of a Markov chain
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Acceptance {
+    Better,
+    RandomWalk,
+    Restart,
+    Lsmc,
+}
+
+/**
+ * IteratedLocalSearch struct
+ *
+ * Iterated local search: the current local optimum is kicked out of its
+ * basin of attraction by a perturbation the local search cannot undo in a
+ * single move, the local search descends into the next local optimum and
+ * the acceptance criterion decides which of the two is kicked next. Every
+ * kick is an iteration, the steps are the moves of all the descents.
+ *
+ * @field distance_matrix: The distance matrix of the TSP problem
+ * @field n: The number of nodes
+ * @field local_search: The greedy or steepest local search descending after every kick
+ * @field perturbation: The kick
+ * @field segment_length: The maximal number of cities in the segment of a RandomSegment kick
+ * @field acceptance: The acceptance criterion
+ * @field restart_after: The number of kicks without improving the best tour after which Restart starts anew
+ * @field temperature: The temperature of Lsmc, relative to the average edge of the current tour
+ * @field termination: The stopping conditions, at least one budget is required
+ * @field seed: The seed of the random number generator
+ * @field rng: The random number generator
+ */
+pub struct IteratedLocalSearch {
+    pub distance_matrix: DistanceMatrix,
+    pub n: usize,
+    pub local_search: LocalSearch,
+    pub perturbation: Perturbation,
+    pub segment_length: usize,
+    pub acceptance: Acceptance,
+    pub restart_after: u32,
+    pub temperature: f32,
+    pub termination: Termination,
+    pub seed: u64,
+    rng: StdRng,
+}
+
+impl IteratedLocalSearch {
+    /**
+     * Create a new IteratedLocalSearch instance descending with greedy with
+     * don't-look bits, restricted to the 10 nearest neighbours
+     *
+     * @param distance_matrix: The distance matrix of the TSP problem
+     * @return: A new IteratedLocalSearch instance
+     */
+    pub fn new(distance_matrix: DistanceMatrix) -> IteratedLocalSearch {
+        let n = distance_matrix.len();
+        let candidates = CandidateList::nearest(&distance_matrix, 10);
+        let local_search = LocalSearch::new(distance_matrix.clone())
+            .with_method(LocalSearchMethod::Greedy)
+            .with_dont_look_bits(true)
+            .with_candidates(candidates);
+        IteratedLocalSearch {
+            distance_matrix,
+            n,
+            local_search,
+            perturbation: Perturbation::DoubleBridge,
+            segment_length: 50,
+            acceptance: Acceptance::Better,
+            restart_after: 100,
+            temperature: 0.1,
+            termination: Termination::new(),
+            seed: 0,
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+
+    /**
+     * Set the local search descending after every kick
+     *
+     * @param method: Greedy or Steepest
+     * @return: The IteratedLocalSearch instance
+     */
+    pub fn with_method(mut self, method: LocalSearchMethod) -> IteratedLocalSearch {
+        self.local_search.method = method;
+        self
+    }
+
+    /**
+     * Set the kick
+     *
+     * @param perturbation: The kick
+     * @return: The IteratedLocalSearch instance
+     */
+    pub fn with_perturbation(mut self, perturbation: Perturbation) -> IteratedLocalSearch {
+        self.perturbation = perturbation;
+        self
+    }
+
+    /**
+     * Set the acceptance criterion
+     *
+     * @param acceptance: The acceptance criterion
+     * @return: The IteratedLocalSearch instance
+     */
+    pub fn with_acceptance(mut self, acceptance: Acceptance) -> IteratedLocalSearch {
+        self.acceptance = acceptance;
+        self
+    }
+
+    /**
+     * Perform an Iterated Local Search on the TSP problem
+     *
+     * @param initial_tour: The starting tour, a random one if None
+     * @return: The best solution found and its distance
+     */
+    pub fn run(&mut self, initial_tour: Option<Vec<u32>>) -> Result<SolveResult, &'static str> {
+        if self.termination.is_unbounded() {
+            return Err("Iterated local search needs a time, evaluation or iteration budget");
+        }
+        if self.local_search.method == LocalSearchMethod::Heuristic {
+            return Err("Iterated local search needs greedy or steepest as its local search");
+        }
+        let initial_tour = utils::initial_tour(initial_tour, self.n, &mut self.rng)?;
+
+        let mut progress = self.termination.start();
+        let mut steps = 0;
+        let mut current = self.descend(initial_tour, &mut progress, &mut steps)?;
+        let mut best = current.clone();
+        progress.improve(best.distance);
+        // Every kick needs three cut points between four non-empty parts
+        if self.n < 4 {
+            return Ok(SolveResult { steps, evaluated: progress.evaluated, seed: self.seed, ..best });
+        }
+
+        let mut no_improvement = 0;
+        while !progress.is_done() {
+            let mut kicked = current.tour.clone();
+            self.kick(&mut kicked);
+            let candidate = self.descend(kicked, &mut progress, &mut steps)?;
+            // The budget ran out before the descent, a replay of the run stops before the kick
+            if candidate.evaluated == 0 {
+                break;
+            }
+
+            let accepted = match self.acceptance {
+                Acceptance::Better | Acceptance::Restart => candidate.distance < current.distance,
+                Acceptance::RandomWalk => true,
+                Acceptance::Lsmc => {
+                    let delta = candidate.distance - current.distance;
+                    let temperature = self.temperature * current.distance / self.n as f32;
+                    delta <= 0.0 || self.rng.gen::<f32>() < (-delta / temperature).exp()
+                },
+            };
+            if accepted {
+                current = candidate;
+            }
+
+            if current.distance < best.distance {
+                best = current.clone();
+                no_improvement = 0;
+            } else {
+                no_improvement += 1;
+            }
+            if self.acceptance == Acceptance::Restart && no_improvement >= self.restart_after && !progress.is_done() {
+                let random_tour = utils::random_permutation(self.n, &mut self.rng);
+                let restarted = self.descend(random_tour, &mut progress, &mut steps)?;
+                if restarted.evaluated == 0 {
+                    break;
+                }
+                current = restarted;
+                if current.distance < best.distance {
+                    best = current.clone();
+                }
+                no_improvement = 0;
+            }
+            progress.iterate(best.distance);
+        }
+        Ok(SolveResult { steps, evaluated: progress.evaluated, seed: self.seed, ..best })
+    }
+
+    /**
+     * Run the local search from a tour within what is left of the budget.
+     *
+     * @param tour: The starting tour
+     * @param progress: The progress of the run, charged with the evaluations of the local search
+     * @param steps: The number of moves so far, increased by the moves of the local search
+     * @return: The local optimum, or the tour reached when the budget ran out
+     */
+    fn descend(&mut self, tour: Vec<u32>, progress: &mut Progress, steps: &mut u32) -> Result<SolveResult, &'static str> {
+        let mut termination = Termination::new();
+        termination.time_limit_ms = self.termination.time_limit_ms.map(|limit| limit - progress.elapsed_ms());
+        termination.max_evaluations = self.termination.max_evaluations.map(|max| max.saturating_sub(progress.evaluated));
+        self.local_search.termination = termination;
+
+        let result = self.local_search.solve_from(Some(tour))?;
+        progress.evaluated += result.evaluated;
+        *steps += result.steps;
+        Ok(result)
+    }
+
+    /**
+     * Apply the perturbation to a tour in place.
+     *
+     * @param tour: The tour, at least 4 cities
+     */
+    fn kick(&mut self, tour: &mut [u32]) {
+        let segment = match self.perturbation {
+            Perturbation::DoubleBridge => tour,
+            Perturbation::RandomSegment => {
+                // Rotating the tour moves the segment to its front without changing the cycle
+                let start = self.rng.gen_range(0..self.n);
+                tour.rotate_left(start);
+                let length = self.segment_length.clamp(4, self.n);
+                &mut tour[..length]
+            },
+        };
+        double_bridge(segment, &mut self.rng);
+    }
+}
+
+/**
+ * Cut a path into A B C D at three random points and reconnect it as A C B D.
+ * Every part is non-empty, so the three edges between them are replaced.
+ *
+ * @param path: The path, at least 4 cities
+ * @param rng: The random number generator
+ */
+fn double_bridge(path: &mut [u32], rng: &mut impl Rng) {
+    let mut cuts = rand::seq::index::sample(rng, path.len() - 1, 3).into_vec();
+    cuts.sort_unstable();
+    let (b, c, d) = (cuts[0] + 1, cuts[1] + 1, cuts[2] + 1);
+    // Swapping the adjacent parts B and C is a rotation of B C
+    path[b..d].rotate_left(c - b);
+}
+
+impl Solver for IteratedLocalSearch {
+    fn name(&self) -> &'static str {
+        "iterated_local_search"
+    }
+
+    fn metadata(&self) -> serde_json::Value {
+        json!({ "local_search": self.local_search.name(), "neighbourhoods": self.local_search.neighbourhoods, "candidates": self.local_search.candidates.as_ref().map(CandidateList::k), "dont_look_bits": self.local_search.dont_look_bits, "perturbation": self.perturbation, "segment_length": self.segment_length, "acceptance": self.acceptance, "restart_after": self.restart_after, "temperature": self.temperature, "termination": self.termination })
+    }
+
+    fn set_termination(&mut self, termination: Termination) {
+        self.termination = termination;
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+        self.local_search.set_seed(seed);
+    }
+
+    fn solve_from(&mut self, initial_tour: Option<Vec<u32>>) -> Result<SolveResult, &'static str> {
+        self.run(initial_tour)
+    }
+}
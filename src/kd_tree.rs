@@ -0,0 +1,187 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::instance::Coordinate;
+
+
+/**
+ * A node found by a query and its squared distance to the query point,
+ * ordered by distance so that a max-heap keeps the farthest on top.
+ */
+#[derive(Debug, Clone, Copy)]
+struct Neighbour {
+    distance: f64,
+    node: u32,
+}
+
+impl PartialEq for Neighbour {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Neighbour {}
+
+impl PartialOrd for Neighbour {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Neighbour {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.total_cmp(&other.distance).then(self.node.cmp(&other.node))
+    }
+}
+
+/**
+ * KdTree struct
+ *
+ * A 2-d tree over the x and y coordinates of the nodes, answering nearest
+ * neighbour queries in the plain Euclidean distance. The tree is implicit:
+ * the subtree of a range of the order has its median as root, split on x
+ * at even depths and on y at odd ones. Removed nodes are skipped by the
+ * queries, and so are subtrees without any node left.
+ *
+ * @field points: The coordinates of every node
+ * @field order: The nodes in tree order
+ * @field index: The index of every node in the order
+ * @field removed: Whether every node was removed
+ * @field remaining: The number of nodes left in the subtree rooted at every index of the order
+ */
+#[derive(Debug, Clone)]
+pub struct KdTree {
+    points: Vec<[f64; 2]>,
+    order: Vec<u32>,
+    index: Vec<usize>,
+    removed: Vec<bool>,
+    remaining: Vec<u32>,
+}
+
+impl KdTree {
+    /**
+     * Build a tree over a set of coordinates.
+     *
+     * @param coordinates: The coordinates of the nodes
+     * @return: The tree
+     */
+    pub fn new(coordinates: &[Coordinate]) -> KdTree {
+        let points: Vec<[f64; 2]> = coordinates.iter().map(|c| [c.x, c.y]).collect();
+        let mut order: Vec<u32> = (0..points.len() as u32).collect();
+        build(&mut order, &points, 0);
+        let mut index = vec![0; order.len()];
+        for (i, &node) in order.iter().enumerate() {
+            index[node as usize] = i;
+        }
+        let mut remaining = vec![0; order.len()];
+        count(&mut remaining, 0, order.len());
+        let removed = vec![false; order.len()];
+        KdTree { points, order, index, removed, remaining }
+    }
+
+    /**
+     * Remove a node from the tree, later queries ignore it.
+     *
+     * @param node: The node
+     */
+    pub fn remove(&mut self, node: u32) {
+        if self.removed[node as usize] { return; }
+        self.removed[node as usize] = true;
+        let target = self.index[node as usize];
+        let (mut low, mut high) = (0, self.order.len());
+        while low < high {
+            let middle = low + (high - low) / 2;
+            self.remaining[middle] -= 1;
+            if target == middle { break; }
+            if target < middle { high = middle } else { low = middle + 1 }
+        }
+    }
+
+    /**
+     * The k nodes nearest to a point, nearest first.
+     *
+     * @param point: The query point
+     * @param k: The number of nodes
+     * @param skip: Nodes the query ignores, e.g. the node at the query point
+     * @return: The nodes
+     */
+    pub fn nearest(&self, point: &Coordinate, k: usize, skip: impl Fn(u32) -> bool) -> Vec<u32> {
+        self.nearest_counted(point, k, skip, &mut 0)
+    }
+
+    /**
+     * The k nodes nearest to a point, nearest first, counting the distances
+     * computed on the way.
+     *
+     * @param point: The query point
+     * @param k: The number of nodes
+     * @param skip: Nodes the query ignores, e.g. the node at the query point
+     * @param evaluated: Incremented for every node the distance to is computed
+     * @return: The nodes
+     */
+    pub fn nearest_counted(&self, point: &Coordinate, k: usize, skip: impl Fn(u32) -> bool, evaluated: &mut u32) -> Vec<u32> {
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        if k > 0 {
+            self.search(0, self.order.len(), 0, [point.x, point.y], k, &skip, &mut heap, evaluated);
+        }
+        heap.into_sorted_vec().into_iter().map(|neighbour| neighbour.node).collect()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn search(&self, low: usize, high: usize, depth: usize, point: [f64; 2], k: usize, skip: &impl Fn(u32) -> bool, heap: &mut BinaryHeap<Neighbour>, evaluated: &mut u32) {
+        if low >= high { return; }
+        let middle = low + (high - low) / 2;
+        if self.remaining[middle] == 0 { return; }
+        let node = self.order[middle];
+        let [x, y] = self.points[node as usize];
+
+        if !self.removed[node as usize] && !skip(node) {
+            *evaluated += 1;
+            let distance = (point[0] - x).powi(2) + (point[1] - y).powi(2);
+            if heap.len() < k {
+                heap.push(Neighbour { distance, node });
+            } else if distance < heap.peek().unwrap().distance {
+                heap.pop();
+                heap.push(Neighbour { distance, node });
+            }
+        }
+
+        let axis = depth % 2;
+        let difference = point[axis] - self.points[node as usize][axis];
+        let (near, far) = if difference < 0.0 {
+            ((low, middle), (middle + 1, high))
+        } else {
+            ((middle + 1, high), (low, middle))
+        };
+        self.search(near.0, near.1, depth + 1, point, k, skip, heap, evaluated);
+        // The other side can only hold closer nodes if the splitting line is closer
+        if heap.len() < k || difference * difference < heap.peek().unwrap().distance {
+            self.search(far.0, far.1, depth + 1, point, k, skip, heap, evaluated);
+        }
+    }
+}
+
+/**
+ * Arrange a range of nodes so that its median on the splitting axis is in
+ * the middle, with the smaller ones before it, and recurse on both halves.
+ */
+fn build(order: &mut [u32], points: &[[f64; 2]], depth: usize) {
+    if order.len() <= 1 { return; }
+    let middle = order.len() / 2;
+    let axis = depth % 2;
+    order.select_nth_unstable_by(middle, |&a, &b| points[a as usize][axis].total_cmp(&points[b as usize][axis]));
+    let (left, right) = order.split_at_mut(middle);
+    build(left, points, depth + 1);
+    build(&mut right[1..], points, depth + 1);
+}
+
+/**
+ * Count the nodes in the subtree rooted at the middle of a range and in all
+ * the subtrees below it.
+ */
+fn count(remaining: &mut [u32], low: usize, high: usize) -> u32 {
+    if low >= high { return 0; }
+    let middle = low + (high - low) / 2;
+    remaining[middle] = 1 + count(remaining, low, middle) + count(remaining, middle + 1, high);
+    remaining[middle]
+}
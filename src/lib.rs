@@ -1,6 +1,20 @@
 pub mod utils;
+pub mod instance;
+pub mod distance;
+pub mod solver;
+pub mod termination;
+pub mod tour;
+pub mod neighbourhood;
+pub mod kd_tree;
+pub mod candidate_list;
+pub mod spanning_tree;
+pub mod lower_bound;
 
+pub mod construction;
 pub mod local_search; 
+pub mod lin_kernighan;
+pub mod exact;
 pub mod random; 
 pub mod simulated_annealing;
-pub mod tabu_search;
\ No newline at end of file
+pub mod tabu_search;
+pub mod iterated_local_search;
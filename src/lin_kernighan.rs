@@ -0,0 +1,325 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde_json::json;
+
+use crate::utils;
+use crate::candidate_list::CandidateList;
+use crate::distance::DistanceMatrix;
+use crate::solver::{SolveResult, Solver};
+use crate::termination::{Progress, Termination};
+use crate::tour::{ArrayTour, Tour, TourLength, TwoLevelList};
+
+/**
+ * Improvements smaller than this are rounding noise.
+ */
+const EPSILON: f32 = 1e-4;
+
+/**
+ * LinKernighan struct
+ *
+ * Variable-depth local search in the style of Lin and Kernighan. From every
+ * node t1, a chain of 2-opt moves is built: the edge (t1, t2) is removed, an
+ * edge (t2, t3) to a candidate neighbour of t2 is added as long as the
+ * partial gain stays positive, the edge (t4, t3) is removed and the tour is
+ * closed with (t1, t4), which is the edge removed by the next step. The chain
+ * is cut back to its best prefix, so a step is only kept if it pays off.
+ *
+ * @field distance_matrix: The distance matrix of the TSP problem
+ * @field n: The number of nodes
+ * @field symmetric: Whether the distance matrix is symmetric
+ * @field candidates: The candidate neighbours t3 is chosen from
+ * @field max_depth: The maximal number of 2-opt moves in a chain
+ * @field breadth: The number of choices of t3 tried for the first step
+ * @field two_level_list: Whether the tour is kept in a TwoLevelList rather than an ArrayTour
+ * @field termination: Additional stopping conditions, the search always stops in a local optimum
+ * @field seed: The seed of the random number generator
+ * @field rng: The random number generator
+ */
+pub struct LinKernighan {
+    pub distance_matrix: DistanceMatrix,
+    pub n: usize,
+    pub symmetric: bool,
+    pub candidates: CandidateList,
+    pub max_depth: usize,
+    pub breadth: usize,
+    pub two_level_list: bool,
+    pub termination: Termination,
+    pub seed: u64,
+    rng: StdRng,
+}
+
+impl LinKernighan {
+    /**
+     * Create a new LinKernighan instance with the 10 nearest neighbours as candidates
+     *
+     * @param distance_matrix: The distance matrix of the TSP problem
+     * @return: A new LinKernighan instance
+     */
+    pub fn new(distance_matrix: DistanceMatrix) -> LinKernighan {
+        let n = distance_matrix.len();
+        let symmetric = distance_matrix.is_symmetric();
+        let candidates = CandidateList::nearest(&distance_matrix, 10);
+        LinKernighan {
+            distance_matrix,
+            n,
+            symmetric,
+            candidates,
+            max_depth: 50,
+            breadth: 5,
+            // Reversals in an array get slower than in the list from a few thousand nodes on
+            two_level_list: n > 5_000,
+            termination: Termination::new(),
+            seed: 0,
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+
+    /**
+     * Perform a Lin-Kernighan search on the TSP problem
+     *
+     * @param initial_tour: The starting tour, a random one if None
+     * @return: The best solution found and its distance
+     */
+    pub fn run(&mut self, initial_tour: Option<Vec<u32>>) -> Result<SolveResult, &'static str> {
+        let initial_tour = utils::initial_tour(initial_tour, self.n, &mut self.rng)?;
+        let mut length = TourLength::new(&initial_tour, &self.distance_matrix);
+
+        let mut progress = self.termination.start();
+        progress.improve(length.get());
+
+        let tour = if self.two_level_list {
+            self.search(TwoLevelList::new(&initial_tour), &mut length, &mut progress)
+        } else {
+            self.search(ArrayTour::new(initial_tour), &mut length, &mut progress)
+        };
+
+        length.verify(&tour, &self.distance_matrix);
+        Ok(SolveResult { tour, distance: length.get(), steps: progress.iterations, evaluated: progress.evaluated, seed: self.seed })
+    }
+
+    /**
+     * Try every t1 until none of them improves the tour.
+     *
+     * @param tour: The starting tour
+     * @param length: Its length, kept up to date
+     * @param progress: The progress of the run
+     * @return: The cities of the final tour in order
+     */
+    fn search<T: Tour>(&self, mut tour: T, length: &mut TourLength, progress: &mut Progress) -> Vec<u32> {
+        let mut improved = true;
+        while improved && !progress.is_done() {
+            improved = false;
+            for t1 in 0..self.n as u32 {
+                // Break either tour edge of t1
+                for forward in [true, false] {
+                    if progress.is_done() { break; }
+                    let delta = self.improve_from(t1, forward, &mut tour, progress);
+                    if delta < 0.0 {
+                        length.add(delta);
+                        if length.is_due() {
+                            length.verify(&tour.order(), &self.distance_matrix);
+                        }
+                        progress.iterate(length.get());
+                        improved = true;
+                    }
+                }
+            }
+        }
+        tour.order()
+    }
+
+    /**
+     * Try to improve the tour by removing the edge between t1 and its
+     * successor or predecessor. Going backwards mirrors every step: t2
+     * precedes t1 and t4 follows t3. The best few choices of t3 are tried
+     * in turn, the deeper steps only follow the best one.
+     *
+     * @param t1: The first node of the chain
+     * @param forward: Whether t2 is the successor of t1
+     * @param tour: The current tour, modified in place
+     * @param progress: The progress of the run
+     * @return: The delta fitness of the kept moves, 0 if none is kept
+     */
+    fn improve_from<T: Tour>(&self, t1: u32, forward: bool, tour: &mut T, progress: &mut Progress) -> f32 {
+        let t2 = neighbour(tour, t1, forward);
+        let gain = self.edge(t1, t2, forward);
+        for (t3, t4) in self.extensions(t1, t2, gain, &[], forward, tour, progress).into_iter().take(self.breadth) {
+            let delta = self.chain(t1, t2, t3, t4, forward, tour, progress);
+            if delta < 0.0 {
+                return delta;
+            }
+        }
+        0.0
+    }
+
+    /**
+     * The choices of t3, and the matching t4, satisfying the gain criterion,
+     * sorted by decreasing d(t4, t3) - d(t2, t3).
+     *
+     * @param t1: The first node of the chain
+     * @param t2: The last node of the chain
+     * @param gain: The sum of the removed minus the added edges, without the closing edge
+     * @param added: The edges added by the chain so far, they are not removed again
+     * @return: The pairs (t3, t4)
+     */
+    #[allow(clippy::too_many_arguments)]
+    fn extensions<T: Tour>(&self, t1: u32, t2: u32, gain: f32, added: &[(u32, u32)], forward: bool, tour: &T, progress: &mut Progress) -> Vec<(u32, u32)> {
+        let next_t2 = neighbour(tour, t2, forward);
+        let mut extensions = Vec::new();
+        for &t3 in self.candidates.get(t2 as usize) {
+            if gain - self.edge(t2, t3, forward) <= 0.0 {
+                // Candidates are sorted by d(t2, t3), no farther one satisfies the gain criterion either
+                if forward || self.symmetric { break; }
+                continue;
+            }
+            if t3 == t1 || t3 == next_t2 { continue; }
+            let t4 = neighbour(tour, t3, !forward);
+            if added.contains(&(t4, t3)) || added.contains(&(t3, t4)) { continue; }
+            progress.evaluate();
+
+            extensions.push((t3, t4, self.edge(t4, t3, forward) - self.edge(t2, t3, forward)));
+        }
+        extensions.sort_by(|a, b| b.2.total_cmp(&a.2));
+        extensions.into_iter().map(|(t3, t4, _)| (t3, t4)).collect()
+    }
+
+    /**
+     * Build a chain of 2-opt moves from its first step and keep its best prefix.
+     *
+     * @param t1: The first node of the chain
+     * @param t2: The neighbour of t1 whose edge is removed
+     * @param t3: The first node t2 is joined to
+     * @param t4: The neighbour of t3 whose edge is removed
+     * @return: The delta fitness of the kept moves, 0 if none is kept
+     */
+    #[allow(clippy::too_many_arguments)]
+    fn chain<T: Tour>(&self, t1: u32, mut t2: u32, mut t3: u32, mut t4: u32, forward: bool, tour: &mut T, progress: &mut Progress) -> f32 {
+        // The reversed paths, undone from the back
+        let mut applied: Vec<(u32, u32)> = Vec::new();
+        // Edges added by the chain must not be removed again
+        let mut added: Vec<(u32, u32)> = Vec::new();
+        let mut delta = 0.0;
+        let mut best_delta = 0.0;
+        let mut best_depth = 0;
+        // Sum of the removed minus the added edges, without the closing edge
+        let mut gain = self.edge(t1, t2, forward);
+
+        loop {
+            // Replace (t1, t2) and (t4, t3) by (t1, t4) and (t2, t3), reversing the path t2..t4
+            let (from, to) = if forward { (t2, t4) } else { (t4, t2) };
+            let mut move_delta = self.edge(t1, t4, forward) + self.edge(t2, t3, forward)
+                - self.edge(t1, t2, forward) - self.edge(t4, t3, forward);
+            if !self.symmetric {
+                move_delta += self.reversal_delta(tour, from, to);
+            }
+            tour.reverse(from, to);
+            applied.push((from, to));
+            added.push((t2, t3));
+
+            delta += move_delta;
+            gain += self.edge(t4, t3, forward) - self.edge(t2, t3, forward);
+            if delta < best_delta - EPSILON {
+                best_delta = delta;
+                best_depth = applied.len();
+            }
+            t2 = t4;
+
+            if applied.len() >= self.max_depth || progress.is_done() { break; }
+            match self.extensions(t1, t2, gain, &added, forward, tour, progress).first() {
+                Some(&(next_t3, next_t4)) => (t3, t4) = (next_t3, next_t4),
+                None => break,
+            }
+        }
+
+        // Undo the moves after the best prefix, the path from..to now runs to..from
+        while applied.len() > best_depth {
+            let (from, to) = applied.pop().unwrap();
+            tour.reverse(to, from);
+        }
+        best_delta
+    }
+
+    /**
+     * The length of the edge between a and b as the tour traverses it in
+     * the direction of the chain.
+     */
+    #[inline]
+    fn edge(&self, a: u32, b: u32, forward: bool) -> f32 {
+        if forward {
+            self.distance_matrix.get(a as usize, b as usize)
+        } else {
+            self.distance_matrix.get(b as usize, a as usize)
+        }
+    }
+
+    /**
+     * Calculate the change of length of a path traversed in the opposite direction.
+     *
+     * @param tour: The current tour
+     * @param from: The first node of the path
+     * @param to: The last node of the path
+     * @return: The delta fitness
+     */
+    fn reversal_delta<T: Tour>(&self, tour: &T, from: u32, to: u32) -> f32 {
+        let mut delta = 0.0;
+        let mut a = from;
+        while a != to {
+            let b = tour.next(a);
+            delta += self.distance_matrix.get(b as usize, a as usize) - self.distance_matrix.get(a as usize, b as usize);
+            a = b;
+        }
+        delta
+    }
+}
+
+/**
+ * The node after a node in the direction of the chain.
+ */
+#[inline]
+fn neighbour<T: Tour>(tour: &T, node: u32, forward: bool) -> u32 {
+    if forward { tour.next(node) } else { tour.prev(node) }
+}
+
+impl Solver for LinKernighan {
+    fn name(&self) -> &'static str {
+        "lin_kernighan"
+    }
+
+    fn metadata(&self) -> serde_json::Value {
+        json!({ "candidates": self.candidates.k(), "max_depth": self.max_depth, "breadth": self.breadth, "two_level_list": self.two_level_list, "termination": self.termination })
+    }
+
+    fn set_termination(&mut self, termination: Termination) {
+        self.termination = termination;
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn solve_from(&mut self, initial_tour: Option<Vec<u32>>) -> Result<SolveResult, &'static str> {
+        self.run(initial_tour)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn improves_asymmetric_tours() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for symmetric in [true, false] {
+            for _ in 0..20 {
+                let distance_matrix = utils::random_distance_matrix(15, symmetric, &mut rng);
+                let initial_tour = utils::random_permutation(15, &mut rng);
+                let initial_distance = utils::calculate_tour_distance(&initial_tour, &distance_matrix).unwrap();
+                let mut solver = LinKernighan::new(distance_matrix.clone());
+                let result = solver.solve_from(Some(initial_tour)).unwrap();
+                assert_eq!(result.distance, utils::calculate_tour_distance(&result.tour, &distance_matrix).unwrap());
+                assert!(result.distance <= initial_distance);
+            }
+        }
+    }
+}
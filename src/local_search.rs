@@ -1,5 +1,45 @@
-use rand::Rng;
+use std::collections::VecDeque;
+use std::ops::ControlFlow;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+
 use crate::utils;
+use crate::candidate_list::CandidateList;
+use crate::construction;
+use crate::distance::DistanceMatrix;
+use crate::neighbourhood::{self, Move, Neighbourhood};
+use crate::solver::{SolveResult, Solver};
+use crate::termination::{Progress, Termination};
+use crate::tour::TourLength;
+
+/**
+ * The algorithm run by LocalSearch as a Solver.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocalSearchMethod {
+    Greedy,
+    Steepest,
+    Heuristic,
+}
+
+/**
+ * ScanOrder enum
+ *
+ * The order in which greedy browses the moves.
+ *
+ * @variant Lexicographic: From the first position of the tour on
+ * @variant RandomStart: From a random position of the tour on, drawn anew for every scan
+ * @variant Shuffled: Node by node in a random order, drawn anew for every scan
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScanOrder {
+    Lexicographic,
+    RandomStart,
+    Shuffled,
+}
 
 /**
  * LocalSearch struct
@@ -10,10 +50,28 @@ use crate::utils;
  *
  * @field distance_matrix: The distance matrix of the TSP problem
  * @field n: The number of nodes
+ * @field symmetric: Whether the distance matrix is symmetric
+ * @field method: The algorithm run by solve()
+ * @field neighbourhoods: The neighbourhoods browsed by greedy and steepest, in order
+ * @field candidates: Restrict greedy and steepest to the moves adding a candidate edge, all the moves if None
+ * @field dont_look_bits: Whether greedy only re-examines the nodes touched by recent moves instead of restarting the scan
+ * @field scan_order: The order in which greedy browses the moves, or fills its queue with don't-look bits
+ * @field termination: Additional stopping conditions, the search always stops in a local optimum
+ * @field seed: The seed of the random number generator
+ * @field rng: The random number generator
  */
 pub struct LocalSearch {
-    pub distance_matrix: Vec<Vec<f32>>,
+    pub distance_matrix: DistanceMatrix,
     pub n: usize,
+    pub symmetric: bool,
+    pub method: LocalSearchMethod,
+    pub neighbourhoods: Vec<Neighbourhood>,
+    pub candidates: Option<CandidateList>,
+    pub dont_look_bits: bool,
+    pub scan_order: ScanOrder,
+    pub termination: Termination,
+    pub seed: u64,
+    rng: StdRng,
 }
 
 impl LocalSearch {
@@ -23,140 +81,339 @@ impl LocalSearch {
      * @param distance_matrix: The distance matrix of the TSP problem
      * @return: A new LocalSearch instance
      */
-    pub fn new(distance_matrix: Vec<Vec<f32>>) -> LocalSearch {
+    pub fn new(distance_matrix: DistanceMatrix) -> LocalSearch {
         let n = distance_matrix.len();
+        let symmetric = distance_matrix.is_symmetric();
         LocalSearch {
             distance_matrix,
             n,
+            symmetric,
+            method: LocalSearchMethod::Steepest,
+            neighbourhoods: vec![Neighbourhood::TwoOpt],
+            candidates: None,
+            dont_look_bits: false,
+            scan_order: ScanOrder::Lexicographic,
+            termination: Termination::new(),
+            seed: 0,
+            rng: StdRng::seed_from_u64(0),
         }
     }
 
+    /**
+     * Set the algorithm run by solve()
+     *
+     * @param method: The algorithm
+     * @return: The LocalSearch instance
+     */
+    pub fn with_method(mut self, method: LocalSearchMethod) -> LocalSearch {
+        self.method = method;
+        self
+    }
+
+    /**
+     * Set the neighbourhoods browsed by greedy and steepest
+     *
+     * @param neighbourhoods: The neighbourhoods, in order
+     * @return: The LocalSearch instance
+     */
+    pub fn with_neighbourhoods(mut self, neighbourhoods: Vec<Neighbourhood>) -> LocalSearch {
+        self.neighbourhoods = neighbourhoods;
+        self
+    }
+
+    /**
+     * Restrict greedy and steepest to the moves adding a candidate edge
+     *
+     * @param candidates: The candidate lists
+     * @return: The LocalSearch instance
+     */
+    pub fn with_candidates(mut self, candidates: CandidateList) -> LocalSearch {
+        self.candidates = Some(candidates);
+        self
+    }
+
+    /**
+     * Let greedy re-examine only the nodes touched by recent moves
+     *
+     * @param dont_look_bits: Whether to use don't-look bits
+     * @return: The LocalSearch instance
+     */
+    pub fn with_dont_look_bits(mut self, dont_look_bits: bool) -> LocalSearch {
+        self.dont_look_bits = dont_look_bits;
+        self
+    }
+
+    /**
+     * Set the order in which greedy browses the moves
+     *
+     * @param scan_order: The order
+     * @return: The LocalSearch instance
+     */
+    pub fn with_scan_order(mut self, scan_order: ScanOrder) -> LocalSearch {
+        self.scan_order = scan_order;
+        self
+    }
+
 
     /**
      * Perform a Greedy Local Search on the TSP problem
      *
+     * @param initial_tour: The starting tour, a random one if None
      * @return: The best solution found and its distance
      */
-    pub fn greedy(&mut self) -> Result<(Vec<u32>, f32, u32, u32), &'static str> {
-        let mut current_tour = utils::random_permutation(self.n);
-        let mut best_tour = current_tour.clone();
-
-        let mut evaluated = 0;
-        let mut steps = 0;
-
-        let mut improvement = true;
-        while improvement {
-            improvement = false;
-            // Intra-route neighbourhood: Iterate all distinct 2-edge pairs
-            for i in 0..self.n {
-                let next_i = (i + 1) % self.n;
-                for j in i + 2..self.n {
-                    let next_j = (j + 1) % self.n;
-                    // Skip directly proceeding edge
-                    if next_j == i { continue; }
-                    // Calculated delta fitness
-                    let delta = utils::get_delta_intra_route(&self.distance_matrix,current_tour[i], current_tour[next_i], current_tour[j], current_tour[next_j]);
-                    evaluated += 1;
-
-                    if delta < 0.0 {
-                        best_tour = utils::swap_2_edges(&current_tour, next_i, j, best_tour);
-                        improvement = true;
-                        break;
+    pub fn greedy(&mut self, initial_tour: Option<Vec<u32>>) -> Result<SolveResult, &'static str> {
+        let mut current_tour = utils::initial_tour(initial_tour, self.n, &mut self.rng)?;
+        let mut length = TourLength::new(&current_tour, &self.distance_matrix);
+
+        let mut progress = self.termination.start();
+        progress.improve(length.get());
+
+        if self.dont_look_bits {
+            // Only the nodes whose edges changed since they were last examined are queued
+            let mut queue: VecDeque<u32> = match self.scan_order {
+                ScanOrder::Lexicographic => current_tour.iter().copied().collect(),
+                ScanOrder::RandomStart => {
+                    let offset = self.rng.gen_range(0..self.n);
+                    current_tour[offset..].iter().chain(&current_tour[..offset]).copied().collect()
+                },
+                ScanOrder::Shuffled => utils::random_permutation(self.n, &mut self.rng).into(),
+            };
+            let mut queued = vec![true; self.n];
+            let mut position = utils::positions(&current_tour);
+            while let Some(node) = queue.pop_front() {
+                if progress.is_done() { break; }
+                queued[node as usize] = false;
+                let at = Some((position.as_slice(), position[node as usize]));
+                if let Some((best_move, delta)) = self.first_improving_move(&current_tour, at, &mut progress) {
+                    for touched in best_move.touched(&current_tour, self.symmetric) {
+                        if !queued[touched as usize] {
+                            queued[touched as usize] = true;
+                            queue.push_back(touched);
+                        }
                     }
+                    best_move.apply(&mut current_tour);
+                    for p in best_move.changed_positions(self.n) {
+                        position[current_tour[p] as usize] = p;
+                    }
+                    length.add(delta);
+                    length.check(&current_tour, &self.distance_matrix);
+                    progress.iterate(length.get());
                 }
-                if improvement {
-                    current_tour = best_tour.clone();
-                    steps += 1;
-                    break;
+            }
+        } else {
+            while !progress.is_done() {
+                let found = match self.scan_order {
+                    ScanOrder::Lexicographic => self.first_improving_move(&current_tour, None, &mut progress),
+                    ScanOrder::RandomStart => {
+                        // Rotating the tour starts the scan elsewhere without changing the cycle
+                        let offset = self.rng.gen_range(0..self.n);
+                        current_tour.rotate_left(offset);
+                        self.first_improving_move(&current_tour, None, &mut progress)
+                    },
+                    ScanOrder::Shuffled => {
+                        let position = utils::positions(&current_tour);
+                        utils::random_permutation(self.n, &mut self.rng).into_iter().find_map(|node| {
+                            self.first_improving_move(&current_tour, Some((&position, position[node as usize])), &mut progress)
+                        })
+                    },
+                };
+                match found {
+                    Some((best_move, delta)) => {
+                        best_move.apply(&mut current_tour);
+                        length.add(delta);
+                        length.check(&current_tour, &self.distance_matrix);
+                        progress.iterate(length.get());
+                    },
+                    None => break,
                 }
             }
         }
-        let distance = utils::calculate_tour_distance(&current_tour, &self.distance_matrix).unwrap();
-        Ok((current_tour, distance, steps, evaluated))
+        length.verify(&current_tour, &self.distance_matrix);
+        Ok(SolveResult { tour: current_tour, distance: length.get(), steps: progress.iterations, evaluated: progress.evaluated, seed: self.seed })
+    }
+
+    /**
+     * Find the first improving move, either among all the moves or among the
+     * moves of the node at a position.
+     *
+     * @param tour: The current tour
+     * @param at: The position of every node and the position of the node, all the moves if None
+     * @param progress: The progress of the run
+     * @return: The move and its delta fitness, None in a local optimum or once the run is done
+     */
+    fn first_improving_move(&self, tour: &[u32], at: Option<(&[usize], usize)>, progress: &mut Progress) -> Option<(Move, f32)> {
+        let evaluate = |possible_move: Move| {
+            if progress.is_done() { return ControlFlow::Break(None); }
+            // Calculated delta fitness
+            let delta = possible_move.delta(&self.distance_matrix, tour, self.symmetric);
+            progress.evaluate();
+
+            if delta < 0.0 {
+                return ControlFlow::Break(Some((possible_move, delta)));
+            }
+            ControlFlow::Continue(())
+        };
+        let found = match at {
+            Some((position, at)) => neighbourhood::for_each_move_at(&self.neighbourhoods, tour, position, at, self.candidates.as_ref(), evaluate),
+            None => neighbourhood::for_each_move(&self.neighbourhoods, tour, self.candidates.as_ref(), evaluate),
+        };
+        match found {
+            ControlFlow::Break(found) => found,
+            ControlFlow::Continue(()) => None,
+        }
     }
 
     /**
      * Perform a Steepest Local Search on the TSP problem
      *
+     * @param initial_tour: The starting tour, a random one if None
      * @return: The best solution found and its distance
      */
-    pub fn steepest(&mut self) -> Result<(Vec<u32>, f32, u32, u32), &'static str> {
-        let mut current_tour = utils::random_permutation(self.n);
-        let mut best_tour = current_tour.clone();
-
-        let mut evaluated = 0;
-        let mut steps = 0;
-
-        let mut best_delta: f32 = 0.0;
-        let mut improvement = true;
-        while improvement {
-            improvement = false;
-            // Intra-route neighbourhood: Iterate all distinct 2-edge pairs
-            for i in 0..self.n {
-                let next_i = (i + 1) % self.n;
-                for j in i + 2..self.n {
-                    let next_j = (j + 1) % self.n;
-                    // Skip directly proceeding edge
-                    if next_j == i { continue; }
-                    // Calculated delta fitness
-                    let delta = utils::get_delta_intra_route(&self.distance_matrix, current_tour[i], current_tour[next_i], current_tour[j], current_tour[next_j]);
-                    evaluated += 1;
-
-                    if delta < best_delta {
-                        best_tour = utils::swap_2_edges(&current_tour, next_i, j, best_tour);
-                        best_delta = delta;
-                        improvement = true;
-                    }
+    pub fn steepest(&mut self, initial_tour: Option<Vec<u32>>) -> Result<SolveResult, &'static str> {
+        let mut current_tour = utils::initial_tour(initial_tour, self.n, &mut self.rng)?;
+        let mut length = TourLength::new(&current_tour, &self.distance_matrix);
+
+        let mut progress = self.termination.start();
+        progress.improve(length.get());
+
+        while !progress.is_done() {
+            // Find the best improving move
+            let mut best: Option<(Move, f32)> = None;
+            let _ = neighbourhood::for_each_move(&self.neighbourhoods, &current_tour, self.candidates.as_ref(), |possible_move| {
+                if progress.is_done() { return ControlFlow::Break(()); }
+                // Calculated delta fitness
+                let delta = possible_move.delta(&self.distance_matrix, &current_tour, self.symmetric);
+                progress.evaluate();
+
+                if delta < best.map_or(0.0, |(_, best_delta)| best_delta) {
+                    best = Some((possible_move, delta));
                 }
-            }
-            if improvement {
-                current_tour = best_tour.clone();
-                steps += 1;
-                best_delta = 0.0;
+                ControlFlow::Continue(())
+            });
+            match best {
+                Some((best_move, delta)) => {
+                    best_move.apply(&mut current_tour);
+                    length.add(delta);
+                    length.check(&current_tour, &self.distance_matrix);
+                    progress.iterate(length.get());
+                },
+                None => break,
             }
         }
 
-        let distance = utils::calculate_tour_distance(&current_tour, &self.distance_matrix).unwrap();
-        Ok((current_tour, distance, steps, evaluated))
+        length.verify(&current_tour, &self.distance_matrix);
+        Ok(SolveResult { tour: current_tour, distance: length.get(), steps: progress.iterations, evaluated: progress.evaluated, seed: self.seed })
     }
 
 
     /**
-     * Perform a Heuristic search on the TSP problem
+     * Perform a Heuristic search on the TSP problem: the nearest neighbour
+     * heuristic from a random city
      *
      * @return: The best solution found and its distance
      */
-    pub fn heuristic(&mut self) -> Result<(Vec<u32>, f32, u32, u32), &'static str> {
-        let mut rng = rand::thread_rng();
+    pub fn heuristic(&mut self) -> Result<SolveResult, &'static str> {
+        if self.n == 0 { return Err("Cannot build a tour without cities"); }
+        // Start with a random city
+        let start = self.rng.gen_range(0..self.n);
+        let (tour, evaluated) = construction::nearest_neighbour(&self.distance_matrix, start, 1, &mut self.rng);
+        let distance = utils::calculate_tour_distance(&tour, &self.distance_matrix).unwrap();
+
+        Ok(SolveResult { tour, distance, steps: self.n as u32 - 1, evaluated, seed: self.seed })
+    }
+}
 
-        let mut visited = vec![false; self.n];
-        let mut tour : Vec<u32> = Vec::with_capacity(self.n);
-        let mut total_distance = 0.0;
+impl Solver for LocalSearch {
+    fn name(&self) -> &'static str {
+        match self.method {
+            LocalSearchMethod::Greedy => "greedy",
+            LocalSearchMethod::Steepest => "steepest",
+            LocalSearchMethod::Heuristic => "heuristic",
+        }
+    }
 
-        // Start with a random city
-        let mut current_city = rng.gen_range(0..self.n);
-        tour.push(current_city as u32);
-        visited[current_city] = true;
-        // Iterate until all cities are visited
-        while tour.len() < self.n {
-            let mut min_distance = f32::MAX;
-            let mut nearest_city = 0;
-            // Find the nearest unvisited city
-            for (city, &is_visited) in visited.iter().enumerate() {
-                if !is_visited && self.distance_matrix[current_city][city] < min_distance {
-                    min_distance = self.distance_matrix[current_city][city];
-                    nearest_city = city;
+    fn metadata(&self) -> serde_json::Value {
+        json!({ "neighbourhoods": self.neighbourhoods, "candidates": self.candidates.as_ref().map(CandidateList::k), "dont_look_bits": self.dont_look_bits, "scan_order": self.scan_order, "termination": self.termination })
+    }
+
+    fn set_termination(&mut self, termination: Termination) {
+        self.termination = termination;
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn solve_from(&mut self, initial_tour: Option<Vec<u32>>) -> Result<SolveResult, &'static str> {
+        match self.method {
+            LocalSearchMethod::Greedy => self.greedy(initial_tour),
+            LocalSearchMethod::Steepest => self.steepest(initial_tour),
+            // Construction ignores the initial tour
+            LocalSearchMethod::Heuristic => self.heuristic(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /**
+     * Whether no move of the neighbourhoods shortens the tour.
+     */
+    fn is_local_optimum(tour: &[u32], distance_matrix: &DistanceMatrix, neighbourhoods: &[Neighbourhood]) -> bool {
+        let symmetric = distance_matrix.is_symmetric();
+        neighbourhood::for_each_move(neighbourhoods, tour, None, |possible_move| {
+            if possible_move.delta(distance_matrix, tour, symmetric) < -1e-3 { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        }).is_continue()
+    }
+
+    #[test]
+    fn greedy_and_steepest_stop_in_a_local_optimum() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let neighbourhoods = vec![Neighbourhood::TwoOpt, Neighbourhood::OrOpt];
+        for symmetric in [true, false] {
+            let distance_matrix = utils::random_distance_matrix(20, symmetric, &mut rng);
+            let initial_tour = utils::random_permutation(20, &mut rng);
+            for scan_order in [ScanOrder::Lexicographic, ScanOrder::RandomStart, ScanOrder::Shuffled] {
+                for method in [LocalSearchMethod::Greedy, LocalSearchMethod::Steepest] {
+                    let mut solver = LocalSearch::new(distance_matrix.clone())
+                        .with_method(method)
+                        .with_neighbourhoods(neighbourhoods.clone())
+                        .with_scan_order(scan_order);
+                    let result = solver.solve_from(Some(initial_tour.clone())).unwrap();
+                    assert_eq!(result.distance, utils::calculate_tour_distance(&result.tour, &distance_matrix).unwrap());
+                    assert!(is_local_optimum(&result.tour, &distance_matrix, &neighbourhoods), "{:?} {:?}", method, scan_order);
                 }
             }
-            // Move to the nearest city
-            current_city = nearest_city;
-            tour.push(current_city as u32);
-            visited[current_city] = true;
-            total_distance += min_distance;
         }
-        // Add distance from the last city back to the starting city
-        total_distance += self.distance_matrix[tour[self.n - 1] as usize][tour[0] as usize];
+    }
 
-        Ok((tour, total_distance, 0, 0))
+    #[test]
+    fn dont_look_bits_stop_in_a_two_opt_local_optimum() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for symmetric in [true, false] {
+            let distance_matrix = utils::random_distance_matrix(20, symmetric, &mut rng);
+            let initial_tour = utils::random_permutation(20, &mut rng);
+            let initial_distance = utils::calculate_tour_distance(&initial_tour, &distance_matrix).unwrap();
+            for scan_order in [ScanOrder::Lexicographic, ScanOrder::RandomStart, ScanOrder::Shuffled] {
+                for neighbourhoods in [vec![Neighbourhood::TwoOpt], vec![Neighbourhood::TwoOpt, Neighbourhood::OrOpt]] {
+                    let mut solver = LocalSearch::new(distance_matrix.clone())
+                        .with_method(LocalSearchMethod::Greedy)
+                        .with_neighbourhoods(neighbourhoods.clone())
+                        .with_dont_look_bits(true)
+                        .with_scan_order(scan_order);
+                    let result = solver.solve_from(Some(initial_tour.clone())).unwrap();
+                    assert_eq!(result.distance, utils::calculate_tour_distance(&result.tour, &distance_matrix).unwrap());
+                    assert!(result.distance <= initial_distance);
+                    // Only the nodes of a changed edge are looked at again, so a move whose
+                    // own edges did not change, e.g. an Or-opt into a new edge, can be missed
+                    if symmetric && neighbourhoods.len() == 1 {
+                        assert!(is_local_optimum(&result.tour, &distance_matrix, &neighbourhoods), "{:?}", scan_order);
+                    }
+                }
+            }
+        }
     }
 }
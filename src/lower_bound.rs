@@ -0,0 +1,258 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::construction;
+use crate::distance::DistanceMatrix;
+use crate::spanning_tree::SpanningTree;
+use crate::utils;
+
+/**
+ * OneTree struct
+ *
+ * A minimum 1-tree under node penalties: a minimum spanning tree over every
+ * city but city 0, plus the two lightest edges at city 0. An edge (a, b)
+ * weighs min(d(a, b), d(b, a)) + penalty[a] + penalty[b]. Every tour is a
+ * 1-tree, so the weight minus twice the sum of the penalties is a lower
+ * bound on the length of every tour, asymmetric instances included.
+ *
+ * @field edges: The edges of the 1-tree
+ * @field degrees: The number of edges at every city
+ * @field weight: The penalised weight of the edges
+ */
+#[derive(Debug, Clone)]
+pub struct OneTree {
+    pub edges: Vec<(usize, usize)>,
+    pub degrees: Vec<usize>,
+    pub weight: f64,
+}
+
+impl OneTree {
+    /**
+     * Build a minimum 1-tree.
+     *
+     * @param distance_matrix: The distance matrix of the TSP problem, at least 3 cities
+     * @param penalties: The penalty of every city
+     * @return: The 1-tree
+     */
+    pub fn minimum(distance_matrix: &DistanceMatrix, penalties: &[f64]) -> OneTree {
+        let symmetric = distance_matrix.is_symmetric();
+        OneTree::from_weights(distance_matrix.len(), |a, b| {
+            let distance = if symmetric {
+                distance_matrix.get(a, b)
+            } else {
+                distance_matrix.get(a, b).min(distance_matrix.get(b, a))
+            };
+            distance as f64 + penalties[a] + penalties[b]
+        })
+    }
+
+    /**
+     * Build a minimum 1-tree of a complete graph.
+     *
+     * @param n: The number of cities, at least 3
+     * @param weight: The weight of the edge between two cities
+     * @return: The 1-tree
+     */
+    pub fn from_weights(n: usize, weight: impl Fn(usize, usize) -> f64) -> OneTree {
+        // Cities 1..n are 0..n - 1 in the spanning tree
+        let tree = SpanningTree::prim(n - 1, 0, |a, b| weight(a + 1, b + 1));
+        let mut edges: Vec<(usize, usize)> = tree.edges().map(|(a, b)| (a + 1, b + 1)).collect();
+        let mut total = tree.weight;
+
+        let mut lightest: Vec<usize> = (1..n).collect();
+        lightest.select_nth_unstable_by(1, |&a, &b| weight(0, a).total_cmp(&weight(0, b)));
+        for &city in &lightest[..2] {
+            edges.push((0, city));
+            total += weight(0, city);
+        }
+
+        let mut degrees = vec![0; n];
+        for &(a, b) in &edges {
+            degrees[a] += 1;
+            degrees[b] += 1;
+        }
+        OneTree { edges, degrees, weight: total }
+    }
+
+    /**
+     * Whether every city has two edges, in which case the 1-tree is a tour.
+     */
+    pub fn is_tour(&self) -> bool {
+        self.degrees.iter().all(|&degree| degree == 2)
+    }
+}
+
+/**
+ * The distance lookups the 1-trees of HeldKarp::for_size may cost together,
+ * about a minute of Prim.
+ */
+const WORK_BUDGET: f64 = 1e10;
+
+/**
+ * HeldKarp struct
+ *
+ * The Held-Karp lower bound: the largest 1-tree bound over the node
+ * penalties, approached by subgradient optimisation. Each step moves the
+ * penalties along the degrees minus 2 by
+ * step_size * (upper_bound - bound) / |degrees - 2|², where the upper bound
+ * is the length of a nearest neighbour tour, and halves the step size after
+ * a number of steps without improvement.
+ *
+ * @field max_iterations: The maximal number of subgradient steps
+ * @field initial_step_size: The step size of the first steps
+ * @field patience: The number of steps without improvement before the step size is halved
+ */
+#[derive(Debug, Clone)]
+pub struct HeldKarp {
+    pub max_iterations: u32,
+    pub initial_step_size: f64,
+    pub patience: u32,
+}
+
+/**
+ * HeldKarpBound struct
+ *
+ * The result of a Held-Karp run.
+ *
+ * @field bound: The best lower bound found
+ * @field penalties: The penalties giving the best bound
+ * @field iterations: The number of subgradient steps made
+ * @field optimal: Whether the best 1-tree is a tour of a symmetric instance, so the bound is its length and optimal
+ */
+#[derive(Debug, Clone)]
+pub struct HeldKarpBound {
+    pub bound: f64,
+    pub penalties: Vec<f64>,
+    pub iterations: u32,
+    pub optimal: bool,
+}
+
+impl HeldKarp {
+    /**
+     * Create a new HeldKarp instance
+     *
+     * @return: A new HeldKarp instance
+     */
+    pub fn new() -> HeldKarp {
+        HeldKarp { max_iterations: 1000, initial_step_size: 2.0, patience: 20 }
+    }
+
+    /**
+     * Create a HeldKarp instance whose steps fit a fixed amount of work on n
+     * cities. Every step builds a dense 1-tree in O(n²), so the 1000 steps
+     * of new() are kept up to about 3000 cities and fall to a single 1-tree,
+     * still a valid bound, around 100000.
+     *
+     * @param n: The number of cities
+     * @return: A new HeldKarp instance
+     */
+    pub fn for_size(n: usize) -> HeldKarp {
+        let max_iterations = (WORK_BUDGET / (n as f64).powi(2)).clamp(1.0, 1000.0) as u32;
+        HeldKarp::new().with_max_iterations(max_iterations)
+    }
+
+    /**
+     * Set the maximal number of subgradient steps
+     *
+     * @param max_iterations: The number of steps
+     * @return: The HeldKarp instance
+     */
+    pub fn with_max_iterations(mut self, max_iterations: u32) -> HeldKarp {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /**
+     * Compute the lower bound of an instance.
+     *
+     * @param distance_matrix: The distance matrix of the TSP problem
+     * @return: The bound
+     */
+    pub fn bound(&self, distance_matrix: &DistanceMatrix) -> HeldKarpBound {
+        let n = distance_matrix.len();
+        if n < 3 {
+            // The only tour uses every edge
+            let tour: Vec<u32> = (0..n as u32).collect();
+            let bound = utils::tour_length(&tour, distance_matrix);
+            return HeldKarpBound { bound, penalties: vec![0.0; n], iterations: 0, optimal: true };
+        }
+        let (tour, _) = construction::nearest_neighbour(distance_matrix, 0, 1, &mut StdRng::seed_from_u64(0));
+        let upper_bound = utils::tour_length(&tour, distance_matrix);
+
+        let mut best = self.ascend(upper_bound, vec![0.0; n], |penalties| OneTree::minimum(distance_matrix, penalties));
+        // On an asymmetric instance the tour may be shorter in one direction than in the other
+        best.optimal &= distance_matrix.is_symmetric();
+        best
+    }
+
+    /**
+     * Maximise the bound of 1-trees under any weights over the penalties.
+     *
+     * @param upper_bound: The length of a known tour
+     * @param penalties: The penalties to start from
+     * @param one_tree: The minimum 1-tree under some penalties, its weight must include them
+     * @return: The best bound found
+     */
+    pub fn ascend(&self, upper_bound: f64, mut penalties: Vec<f64>, one_tree: impl Fn(&[f64]) -> OneTree) -> HeldKarpBound {
+        let mut best = HeldKarpBound { bound: f64::NEG_INFINITY, penalties: penalties.clone(), iterations: 0, optimal: false };
+        let mut step_size = self.initial_step_size;
+        let mut since_improvement = 0;
+
+        while best.iterations < self.max_iterations {
+            best.iterations += 1;
+            let one_tree = one_tree(&penalties);
+            let bound = one_tree.weight - 2.0 * penalties.iter().sum::<f64>();
+            if bound > best.bound {
+                best.bound = bound;
+                best.penalties.clone_from(&penalties);
+                best.optimal = one_tree.is_tour();
+                since_improvement = 0;
+            } else {
+                since_improvement += 1;
+                if since_improvement >= self.patience {
+                    step_size /= 2.0;
+                    since_improvement = 0;
+                }
+            }
+
+            let norm: usize = one_tree.degrees.iter().map(|&degree| degree.abs_diff(2).pow(2)).sum();
+            // A 1-tree that is a tour cannot be improved on, and a tiny step no longer moves the bound
+            if norm == 0 || step_size < 1e-6 || upper_bound <= bound { break; }
+            let step = step_size * (upper_bound - bound) / norm as f64;
+            for (penalty, &degree) in penalties.iter_mut().zip(&one_tree.degrees) {
+                *penalty += step * (degree as f64 - 2.0);
+            }
+        }
+        best
+    }
+}
+
+impl Default for HeldKarp {
+    fn default() -> Self {
+        HeldKarp::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::Instance;
+
+    #[test]
+    fn steps_fall_with_the_size() {
+        assert_eq!(HeldKarp::for_size(52).max_iterations, 1000);
+        assert_eq!(HeldKarp::for_size(3_000).max_iterations, 1000);
+        assert_eq!(HeldKarp::for_size(10_000).max_iterations, 100);
+        assert_eq!(HeldKarp::for_size(85_900).max_iterations, 1);
+    }
+
+    #[test]
+    fn bound_is_below_the_optimum() {
+        let instance = Instance::from_file("data/berlin52.txt").unwrap();
+        let single = HeldKarp::new().with_max_iterations(1).bound(&instance.distance_matrix);
+        let ascended = HeldKarp::for_size(52).bound(&instance.distance_matrix);
+        assert!(single.bound <= ascended.bound);
+        assert!(ascended.bound <= 7542.0 + 1e-6);
+        assert!(ascended.bound >= 0.99 * 7542.0, "{}", ascended.bound);
+    }
+}
@@ -1,62 +1,48 @@
-use std::io;
+use std::error::Error;
 
 // extern crate glob;
 use glob::glob;
 
 use biam::utils;
+use biam::solver;
+use biam::termination::Termination;
 
-use biam::local_search::LocalSearch;
-use biam::random::Random;
-use biam::simulated_annealing::SimulatedAnnealing;
-use biam::tabu_search::TabuSearch;
 
-
-#[allow(non_snake_case)]
-fn main() -> io::Result<()> {
+fn main() -> Result<(), Box<dyn Error>> {
     // Measurement variables
     let mut time_start;
-    let mut avg_time: f64 = 0.0;
+    let mut avg_time: f64;
 
-   let algorithms = vec!["greedy", "steepest", "random_search", "random_walk", "heuristic", "simulated_annealing", "tabu_search"];
     let runs = 10;
+    // The algorithms that only stop on a budget get the same wall-clock budget, the others stop on their own
+    let termination = Termination::new().with_time_limit(1000.0);
 
     for file_path in glob("./data/*.txt").expect("Failed to read glob pattern") {
         let path = file_path.unwrap().display().to_string();
-        let instance_name = path.split("/").last().unwrap().split(".").next().unwrap();
+        let instance = utils::read_instance(&path)?;
+        let instance_name = instance.name.as_str();
         println!("{:?}", instance_name);
-        let distance_matrix = utils::read_instance(&path)?;
-        let mut solver_LS = LocalSearch::new(distance_matrix.clone());
-        let mut solver_R = Random::new(distance_matrix.clone());
-        let mut solver_SA = SimulatedAnnealing::new(distance_matrix.clone());
-        let mut solver_TS = TabuSearch::new(distance_matrix.clone(), None);
-        solver_SA.determine_initial_temperature();
+        // Scores the results of instances without a published optimum
+        let lower_bound = utils::lower_bound(&instance);
+
+        for algorithm_name in solver::ALGORITHMS {
+            // Skip the algorithms the instance cannot run, e.g. without coordinates
+            let Some(mut solver) = solver::create_solver(algorithm_name, &instance) else { continue; };
+            if solver::BUDGETED.contains(&algorithm_name) {
+                solver.set_termination(termination.clone());
+            }
 
-        for algorithm_name in &algorithms {
             let mut elapsed_time = Vec::new();
-            let mut distances = Vec::new();
-            let mut solutions = Vec::new();
-            let mut steps = Vec::new();
-            let mut evaluated = Vec::new();
-            for _ in 0..runs {
-                solver_R.init_random();
+            let mut results = Vec::new();
+            for run in 0..runs {
+                // Every run is reproducible from its seed, saved with the results
+                solver.set_seed(run);
                 time_start = std::time::Instant::now();
-                let (solution, distance, step, eval) = match *algorithm_name {
-                    "greedy" => solver_LS.greedy().unwrap(),
-                    "steepest" => solver_LS.steepest().unwrap(),
-                    "random_search" => solver_R.search(avg_time).unwrap(),
-                    "random_walk" => solver_R.walk(avg_time).unwrap(),
-                    "heuristic" => solver_LS.heuristic().unwrap(),
-                    "simulated_annealing" => solver_SA.run().unwrap(),
-                    "tabu_search" => solver_TS.run().unwrap(),
-                    _ => panic!("Unknown algorithm"),
-                };
+                let result = solver.solve()?;
                 elapsed_time.push(time_start.elapsed().as_millis());
-                distances.push(distance);
-                solutions.push(solution);
-                steps.push(step);
-                evaluated.push(eval);
+                results.push(result);
             }
-            utils::save_solution(instance_name, algorithm_name, &solutions, &distances, &elapsed_time, &steps, &evaluated);
+            utils::save_solution(instance_name, solver.name(), &solver.metadata(), &results, &elapsed_time, Some(lower_bound));
             avg_time = elapsed_time.iter().sum::<u128>() as f64 / elapsed_time.len() as f64;
             println!("\t{:?}: {:?}", algorithm_name, avg_time);
         }
@@ -0,0 +1,942 @@
+use std::ops::{ControlFlow, RangeInclusive};
+
+use serde::{Serialize, Deserialize};
+
+use crate::candidate_list::CandidateList;
+use crate::distance::DistanceMatrix;
+use crate::utils;
+
+
+/**
+ * Neighbourhood enum
+ *
+ * The neighbourhoods a solver can browse, alone or combined.
+ *
+ * @variant TwoOpt: Exchange of two edges, reversing the segment between them
+ * @variant OrOpt: Relocation of a segment of 1 to 3 nodes, optionally reversed
+ * @variant Swap: Exchange of the positions of two nodes
+ * @variant Insertion: Removal of a node and its insertion at another position
+ * @variant ThreeOpt: Exchange of three edges, with any of the seven reconnections
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Neighbourhood {
+    TwoOpt,
+    OrOpt,
+    Swap,
+    Insertion,
+    ThreeOpt,
+}
+
+/**
+ * Reconnection enum
+ *
+ * The seven ways to join again the segments A, B and C of a tour cut at
+ * three edges, other than the tour itself. Primes denote reversed segments.
+ *
+ * @variant ReverseFirst: A B' C, a 2-opt move
+ * @variant ReverseSecond: A B C', a 2-opt move
+ * @variant ReverseAll: A C' B', a 2-opt move
+ * @variant ReverseBoth: A B' C'
+ * @variant Exchange: A C B, the only one keeping the orientation of all segments
+ * @variant ExchangeReverseFirst: A C B'
+ * @variant ExchangeReverseSecond: A C' B
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Reconnection {
+    ReverseFirst,
+    ReverseSecond,
+    ReverseAll,
+    ReverseBoth,
+    Exchange,
+    ExchangeReverseFirst,
+    ExchangeReverseSecond,
+}
+
+/**
+ * A node at a cut of a 3-opt move: the cut, 0 for i, 1 for j and 2 for k,
+ * and 0 for the node before it or 1 for the node after it.
+ */
+pub type CutEnd = (usize, usize);
+
+/**
+ * All the reconnections, in the order they are browsed.
+ */
+pub const RECONNECTIONS: [Reconnection; 7] = [
+    Reconnection::ReverseFirst,
+    Reconnection::ReverseSecond,
+    Reconnection::ReverseAll,
+    Reconnection::ReverseBoth,
+    Reconnection::Exchange,
+    Reconnection::ExchangeReverseFirst,
+    Reconnection::ExchangeReverseSecond,
+];
+
+impl Reconnection {
+    /**
+     * Which of the segments B and C end up reversed.
+     *
+     * @return: Whether B is reversed, whether C is reversed
+     */
+    pub fn reversed(&self) -> (bool, bool) {
+        match self {
+            Reconnection::ReverseFirst | Reconnection::ExchangeReverseFirst => (true, false),
+            Reconnection::ReverseSecond | Reconnection::ExchangeReverseSecond => (false, true),
+            Reconnection::ReverseAll | Reconnection::ReverseBoth => (true, true),
+            Reconnection::Exchange => (false, false),
+        }
+    }
+
+    /**
+     * The edges the reconnection adds. A 2-opt reconnection adds the edge
+     * of one cut back and lists only the other two.
+     *
+     * @return: The added edges
+     */
+    pub fn added_edges(&self) -> &'static [(CutEnd, CutEnd)] {
+        const I: CutEnd = (0, 0);
+        const NEXT_I: CutEnd = (0, 1);
+        const J: CutEnd = (1, 0);
+        const NEXT_J: CutEnd = (1, 1);
+        const K: CutEnd = (2, 0);
+        const NEXT_K: CutEnd = (2, 1);
+        match self {
+            Reconnection::ReverseFirst => &[(I, J), (NEXT_I, NEXT_J)],
+            Reconnection::ReverseSecond => &[(J, K), (NEXT_J, NEXT_K)],
+            Reconnection::ReverseAll => &[(I, K), (NEXT_I, NEXT_K)],
+            Reconnection::ReverseBoth => &[(I, J), (NEXT_I, K), (NEXT_J, NEXT_K)],
+            Reconnection::Exchange => &[(I, NEXT_J), (K, NEXT_I), (J, NEXT_K)],
+            Reconnection::ExchangeReverseFirst => &[(I, NEXT_J), (K, J), (NEXT_I, NEXT_K)],
+            Reconnection::ExchangeReverseSecond => &[(I, K), (NEXT_J, NEXT_I), (J, NEXT_K)],
+        }
+    }
+
+    /**
+     * Whether the segments B and C swap places.
+     */
+    pub fn exchanges(&self) -> bool {
+        matches!(self, Reconnection::ReverseAll | Reconnection::Exchange
+            | Reconnection::ExchangeReverseFirst | Reconnection::ExchangeReverseSecond)
+    }
+}
+
+/**
+ * The longest segment relocated by Or-opt.
+ */
+pub const OR_OPT_MAX_LENGTH: usize = 3;
+
+/**
+ * Move enum
+ *
+ * A move of one of the neighbourhoods, given by tour positions.
+ *
+ * @variant TwoOpt: Replace edges (i, i + 1) and (j, j + 1) by (i, j) and (i + 1, j + 1), i < j
+ * @variant OrOpt: Move the segment [start, start + length), modulo n, between positions target and target + 1
+ * @variant Swap: Exchange the nodes at positions i and j, i < j
+ * @variant Insertion: Move the node at position from so that it ends up at position to
+ * @variant ThreeOpt: Remove edges (i, i + 1), (j, j + 1) and (k, k + 1), i < j < k, and reconnect the segments
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Move {
+    TwoOpt { i: usize, j: usize },
+    OrOpt { start: usize, length: usize, target: usize, reversed: bool },
+    Swap { i: usize, j: usize },
+    Insertion { from: usize, to: usize },
+    ThreeOpt { i: usize, j: usize, k: usize, reconnection: Reconnection },
+}
+
+impl Move {
+    /**
+     * Calculate the change of the tour length caused by the move.
+     *
+     * @param distance_matrix: The distance matrix
+     * @param tour: The current tour
+     * @param symmetric: Whether the distance matrix is symmetric
+     * @return: The delta fitness
+     */
+    pub fn delta(&self, distance_matrix: &DistanceMatrix, tour: &[u32], symmetric: bool) -> f32 {
+        match *self {
+            Move::TwoOpt { i, j } => utils::get_delta_2opt(distance_matrix, tour, i, j, symmetric),
+            Move::OrOpt { start, length, target, reversed } => {
+                let n = tour.len();
+                let d = |a: u32, b: u32| distance_matrix.get(a as usize, b as usize);
+                let end = (start + length - 1) % n;
+                let (prev, first, last, next) = (tour[(start + n - 1) % n], tour[start], tour[end], tour[(end + 1) % n]);
+                let (a, b) = (tour[target], tour[(target + 1) % n]);
+
+                let removed = d(prev, first) + d(last, next) + d(a, b);
+                let added = if reversed {
+                    d(prev, next) + d(a, last) + d(first, b)
+                } else {
+                    d(prev, next) + d(a, first) + d(last, b)
+                };
+                let mut delta = added - removed;
+                if reversed && !symmetric {
+                    for offset in 0..length - 1 {
+                        let (k, next_k) = ((start + offset) % n, (start + offset + 1) % n);
+                        delta += d(tour[next_k], tour[k]) - d(tour[k], tour[next_k]);
+                    }
+                }
+                delta
+            },
+            Move::Swap { i, j } => {
+                let n = tour.len();
+                let d = |a: u32, b: u32| distance_matrix.get(a as usize, b as usize);
+                let (u, v) = (tour[i], tour[j]);
+                let (prev_i, next_i) = (tour[(i + n - 1) % n], tour[(i + 1) % n]);
+                let (prev_j, next_j) = (tour[(j + n - 1) % n], tour[(j + 1) % n]);
+
+                if j == i + 1 {
+                    // Adjacent nodes: the edge between them is only reversed
+                    d(prev_i, v) + d(v, u) + d(u, next_j) - d(prev_i, u) - d(u, v) - d(v, next_j)
+                } else if (j + 1) % n == i {
+                    // Adjacent through the end of the tour: v precedes u
+                    d(prev_j, u) + d(u, v) + d(v, next_i) - d(prev_j, v) - d(v, u) - d(u, next_i)
+                } else {
+                    d(prev_i, v) + d(v, next_i) + d(prev_j, u) + d(u, next_j)
+                        - d(prev_i, u) - d(u, next_i) - d(prev_j, v) - d(v, next_j)
+                }
+            },
+            Move::Insertion { from, to } => {
+                let n = tour.len();
+                let d = |a: u32, b: u32| distance_matrix.get(a as usize, b as usize);
+                let node = tour[from];
+                let (prev, next) = (tour[(from + n - 1) % n], tour[(from + 1) % n]);
+                // The edge the node is inserted into, it also covers adjacent positions
+                let (a, b) = if from < to {
+                    (tour[to], tour[(to + 1) % n])
+                } else {
+                    (tour[(to + n - 1) % n], tour[to])
+                };
+                d(prev, next) + d(a, node) + d(node, b) - d(prev, node) - d(node, next) - d(a, b)
+            },
+            Move::ThreeOpt { i, j, k, reconnection } => utils::get_delta_3opt(distance_matrix, tour, i, j, k, reconnection, symmetric),
+        }
+    }
+
+    /**
+     * Apply the move to the tour in place, without allocating. It costs
+     * O(n) at worst, the length of the part of the array the move shifts.
+     *
+     * @param tour: The tour to modify
+     */
+    pub fn apply(&self, tour: &mut [u32]) {
+        match *self {
+            Move::TwoOpt { i, j } => tour[i + 1..=j].reverse(),
+            Move::OrOpt { start, length, target, reversed } if start + length > tour.len() => {
+                // Rotating the tour keeps the cycle and makes the segment contiguous
+                let n = tour.len();
+                let shift = start + length - n;
+                tour.rotate_left(shift);
+                Move::OrOpt { start: start - shift, length, target: (target + n - shift) % n, reversed }.apply(tour);
+            },
+            Move::OrOpt { start, length, target, reversed } => {
+                let end = start + length - 1;
+                // The segment goes right after tour[target], the cities in between shift over it
+                let segment = if target > end {
+                    tour[start..=target].rotate_left(length);
+                    target + 1 - length..=target
+                } else {
+                    tour[target + 1..=end].rotate_right(length);
+                    target + 1..=target + length
+                };
+                if reversed {
+                    tour[segment].reverse();
+                }
+            },
+            Move::Swap { i, j } => tour.swap(i, j),
+            Move::Insertion { from, to } => {
+                if from < to {
+                    tour[from..=to].rotate_left(1);
+                } else {
+                    tour[to..=from].rotate_right(1);
+                }
+            },
+            Move::ThreeOpt { i, j, k, reconnection } => {
+                let (reverse_first, reverse_second) = reconnection.reversed();
+                if reverse_first {
+                    tour[i + 1..=j].reverse();
+                }
+                if reverse_second {
+                    tour[j + 1..=k].reverse();
+                }
+                // ReverseAll is C' B', i.e. the reversal of B C
+                if reconnection.exchanges() {
+                    tour[i + 1..=k].rotate_left(j - i);
+                }
+            },
+        }
+    }
+
+    /**
+     * The positions whose node may change when the move is applied, the
+     * rest of the tour keeps its positions. An Or-opt segment wrapping
+     * around the end of the tour rotates the whole tour.
+     *
+     * @param n: The number of nodes
+     * @return: The positions
+     */
+    pub fn changed_positions(&self, n: usize) -> RangeInclusive<usize> {
+        match *self {
+            Move::TwoOpt { i, j } => i + 1..=j,
+            Move::OrOpt { start, length, .. } if start + length > n => 0..=n - 1,
+            Move::OrOpt { start, length, target, .. } => {
+                let end = start + length - 1;
+                if target > end { start..=target } else { target + 1..=end }
+            },
+            Move::Swap { i, j } => i..=j,
+            Move::Insertion { from, to } => from.min(to)..=from.max(to),
+            Move::ThreeOpt { i, k, .. } => i + 1..=k,
+        }
+    }
+
+    /**
+     * The nodes whose neighbours in the tour change with the move. On an
+     * asymmetric instance every node of a reversed path gets its edges
+     * the other way round, so they all count.
+     *
+     * @param tour: The current tour, before the move
+     * @param symmetric: Whether the distance matrix is symmetric
+     * @return: The nodes, possibly repeated
+     */
+    pub fn touched(&self, tour: &[u32], symmetric: bool) -> Vec<u32> {
+        let n = tour.len();
+        let around = |positions: &[usize]| positions.iter().flat_map(|&p| [tour[p], tour[(p + 1) % n]]).collect();
+        let mut touched: Vec<u32> = match *self {
+            Move::TwoOpt { i, j } => around(&[i, j]),
+            Move::OrOpt { start, length, target, .. } => around(&[(start + n - 1) % n, (start + length - 1) % n, target]),
+            Move::Swap { i, j } => around(&[(i + n - 1) % n, i, (j + n - 1) % n, j]),
+            Move::Insertion { from, to } => around(&[(from + n - 1) % n, from, (to + n - 1) % n, to]),
+            Move::ThreeOpt { i, j, k, .. } => around(&[i, j, k]),
+        };
+        if !symmetric {
+            // The start and the length of every reversed path
+            let reversed_paths = match *self {
+                Move::TwoOpt { i, j } => vec![(i + 1, j - i)],
+                Move::OrOpt { start, length, reversed: true, .. } => vec![(start, length)],
+                Move::ThreeOpt { i, j, k, reconnection } => {
+                    let (reverse_first, reverse_second) = reconnection.reversed();
+                    [(reverse_first, (i + 1, j - i)), (reverse_second, (j + 1, k - j))].into_iter().filter_map(|(reverse, path)| reverse.then_some(path)).collect()
+                },
+                _ => Vec::new(),
+            };
+            touched.extend(reversed_paths.into_iter().flat_map(|(start, length)| (start..start + length).map(|p| tour[p % n])));
+        }
+        touched
+    }
+
+    /**
+     * A pair of positions identifying the move, e.g. for a tabu list.
+     * The key of a 3-opt move is its outer cuts (i, k): it ignores the
+     * middle cut j and the reconnection, so making one 3-opt move tabu
+     * also forbids every other 3-opt move with the same outer cuts.
+     *
+     * @return: The pair, first <= second
+     */
+    pub fn key(&self) -> (usize, usize) {
+        match *self {
+            Move::TwoOpt { i, j } => (i, j),
+            Move::OrOpt { start, target, .. } => (start.min(target), start.max(target)),
+            Move::Swap { i, j } => (i, j),
+            Move::Insertion { from, to } => (from.min(to), from.max(to)),
+            Move::ThreeOpt { i, k, .. } => (i, k),
+        }
+    }
+}
+
+/**
+ * Browse the moves of the given neighbourhoods in a fixed order until the
+ * callback breaks. With candidate lists, only the moves joining a node to
+ * one of its candidates are browsed, and a move may come up more than once.
+ *
+ * @param neighbourhoods: The neighbourhoods to browse, in order
+ * @param tour: The current tour
+ * @param candidates: The candidate lists, all the moves are browsed if None
+ * @param f: The callback, called with every move
+ * @return: The value the callback broke with, if any
+ */
+pub fn for_each_move<B>(neighbourhoods: &[Neighbourhood], tour: &[u32], candidates: Option<&CandidateList>, mut f: impl FnMut(Move) -> ControlFlow<B>) -> ControlFlow<B> {
+    let n = tour.len();
+    if let Some(candidates) = candidates {
+        let position = utils::positions(tour);
+        for &neighbourhood in neighbourhoods {
+            for at in 0..n {
+                candidate_moves_at(neighbourhood, tour, &position, at, candidates, &mut f)?;
+            }
+        }
+        return ControlFlow::Continue(());
+    }
+    for neighbourhood in neighbourhoods {
+        match neighbourhood {
+            Neighbourhood::TwoOpt => {
+                // Intra-route neighbourhood: Iterate all distinct 2-edge pairs
+                for i in 0..n {
+                    for j in i + 2..n {
+                        // Skip directly proceeding edge
+                        if (j + 1) % n == i { continue; }
+                        f(Move::TwoOpt { i, j })?;
+                    }
+                }
+            },
+            Neighbourhood::OrOpt => {
+                for length in 1..=OR_OPT_MAX_LENGTH {
+                    // The segment needs two other nodes around it
+                    if length + 2 > n { break; }
+                    // Segments wrap around the end of the tour
+                    for start in 0..n {
+                        let end = (start + length - 1) % n;
+                        // Every position outside [start - 1, end]
+                        for offset in 1..n - length {
+                            let target = (end + offset) % n;
+                            f(Move::OrOpt { start, length, target, reversed: false })?;
+                            // Reversing a single node changes nothing
+                            if length > 1 {
+                                f(Move::OrOpt { start, length, target, reversed: true })?;
+                            }
+                        }
+                    }
+                }
+            },
+            Neighbourhood::Swap => {
+                for i in 0..n {
+                    for j in i + 1..n {
+                        f(Move::Swap { i, j })?;
+                    }
+                }
+            },
+            Neighbourhood::Insertion => {
+                for from in 0..n {
+                    for to in 0..n {
+                        // Moving a node one step back is the same as moving its predecessor one step forward
+                        if to == from || to + 1 == from { continue; }
+                        if is_rotation(from, to, n) { continue; }
+                        f(Move::Insertion { from, to })?;
+                    }
+                }
+            },
+            Neighbourhood::ThreeOpt => {
+                // Iterate all triples of distinct edges, B and C are never empty
+                for i in 0..n {
+                    for j in i + 1..n {
+                        for k in j + 1..n {
+                            for reconnection in RECONNECTIONS {
+                                f(Move::ThreeOpt { i, j, k, reconnection })?;
+                            }
+                        }
+                    }
+                }
+            },
+        }
+    }
+    ControlFlow::Continue(())
+}
+
+/**
+ * Whether moving the node at position from to position to only rotates the
+ * tour, i.e. the first node goes to the end or the last one to the start.
+ */
+fn is_rotation(from: usize, to: usize, n: usize) -> bool {
+    (from == 0 && to == n - 1) || (from == n - 1 && to == 0)
+}
+
+/**
+ * Browse the moves of the given neighbourhoods changing an edge of the node
+ * at a given position, e.g. for a don't-look bits queue. With candidate
+ * lists, only the moves joining this node to one of its candidates are browsed.
+ *
+ * @param neighbourhoods: The neighbourhoods to browse, in order
+ * @param tour: The current tour
+ * @param position: The position of every node in the tour
+ * @param at: The position of the node
+ * @param candidates: The candidate lists, all the moves of the node are browsed if None
+ * @param f: The callback, called with every move
+ * @return: The value the callback broke with, if any
+ */
+pub fn for_each_move_at<B>(neighbourhoods: &[Neighbourhood], tour: &[u32], position: &[usize], at: usize, candidates: Option<&CandidateList>, mut f: impl FnMut(Move) -> ControlFlow<B>) -> ControlFlow<B> {
+    for &neighbourhood in neighbourhoods {
+        match candidates {
+            Some(candidates) => candidate_moves_at(neighbourhood, tour, position, at, candidates, &mut f)?,
+            None => moves_at(neighbourhood, tour.len(), at, &mut f)?,
+        }
+    }
+    ControlFlow::Continue(())
+}
+
+/**
+ * Browse all the moves of a neighbourhood changing an edge of the node at a position.
+ */
+fn moves_at<B>(neighbourhood: Neighbourhood, n: usize, at: usize, f: &mut impl FnMut(Move) -> ControlFlow<B>) -> ControlFlow<B> {
+    match neighbourhood {
+        Neighbourhood::TwoOpt => {
+            // The edge after the node, then the one before it
+            for edge in [at, (at + n - 1) % n] {
+                for other in 0..n {
+                    let (i, j) = (edge.min(other), edge.max(other));
+                    if j < i + 2 || (j + 1) % n == i { continue; }
+                    f(Move::TwoOpt { i, j })?;
+                }
+            }
+        },
+        Neighbourhood::OrOpt => {
+            for length in 1..=OR_OPT_MAX_LENGTH {
+                if length + 2 > n { break; }
+                // The segments starting and ending at the node
+                let mut starts = vec![at];
+                if length > 1 {
+                    starts.push((at + n + 1 - length) % n);
+                }
+                for start in starts {
+                    let end = (start + length - 1) % n;
+                    for offset in 1..n - length {
+                        let target = (end + offset) % n;
+                        f(Move::OrOpt { start, length, target, reversed: false })?;
+                        if length > 1 {
+                            f(Move::OrOpt { start, length, target, reversed: true })?;
+                        }
+                    }
+                }
+            }
+        },
+        Neighbourhood::Swap => {
+            for other in 0..n {
+                if other == at { continue; }
+                f(Move::Swap { i: at.min(other), j: at.max(other) })?;
+            }
+        },
+        Neighbourhood::Insertion => {
+            for to in 0..n {
+                if to == at || is_rotation(at, to, n) { continue; }
+                f(Move::Insertion { from: at, to })?;
+            }
+        },
+        Neighbourhood::ThreeOpt => {
+            // The node starts the first, the second or the third removed edge
+            let triples = (at + 1..n).flat_map(|j| (j + 1..n).map(move |k| (at, j, k)))
+                .chain((0..at).flat_map(|i| (at + 1..n).map(move |k| (i, at, k))))
+                .chain((0..at).flat_map(|i| (i + 1..at).map(move |j| (i, j, at))));
+            for (i, j, k) in triples {
+                for reconnection in RECONNECTIONS {
+                    f(Move::ThreeOpt { i, j, k, reconnection })?;
+                }
+            }
+        },
+    }
+    ControlFlow::Continue(())
+}
+
+/**
+ * Browse the moves of a neighbourhood adding an edge between the node at a
+ * position and one of its candidates. A 3-opt move places its third cut so
+ * that another added edge joins a node to one of its candidates as well.
+ */
+fn candidate_moves_at<B>(neighbourhood: Neighbourhood, tour: &[u32], position: &[usize], at: usize, candidates: &CandidateList, f: &mut impl FnMut(Move) -> ControlFlow<B>) -> ControlFlow<B> {
+    let n = tour.len();
+    let node = tour[at] as usize;
+    // A candidate already next to the node gives no new edge
+    let adjacent = |p: usize| p == (at + 1) % n || p == (at + n - 1) % n;
+
+    match neighbourhood {
+        Neighbourhood::TwoOpt => {
+            for &candidate in candidates.get(node) {
+                let p = position[candidate as usize];
+                if adjacent(p) { continue; }
+                // The candidate edge as the first or as the second new edge
+                for (a, b) in [(at, p), ((at + n - 1) % n, (p + n - 1) % n)] {
+                    let (i, j) = (a.min(b), a.max(b));
+                    if j < i + 2 || (j + 1) % n == i { continue; }
+                    f(Move::TwoOpt { i, j })?;
+                }
+            }
+        },
+        Neighbourhood::OrOpt => {
+            for length in 1..=OR_OPT_MAX_LENGTH {
+                if length + 2 > n { break; }
+                // The segment starting at the node, right after or right before a candidate
+                for &candidate in candidates.get(node) {
+                    let p = position[candidate as usize];
+                    if adjacent(p) { continue; }
+                    for (target, reversed) in [(p, false), ((p + n - 1) % n, length > 1)] {
+                        if is_outside(at, length, target, n) {
+                            f(Move::OrOpt { start: at, length, target, reversed })?;
+                        }
+                    }
+                }
+                // The segment ending at the node, right after or right before a candidate
+                if length > 1 {
+                    let start = (at + n + 1 - length) % n;
+                    for &candidate in candidates.get(node) {
+                        let p = position[candidate as usize];
+                        if adjacent(p) { continue; }
+                        for (target, reversed) in [(p, true), ((p + n - 1) % n, false)] {
+                            if is_outside(start, length, target, n) {
+                                f(Move::OrOpt { start, length, target, reversed })?;
+                            }
+                        }
+                    }
+                }
+            }
+        },
+        Neighbourhood::Swap => {
+            for &candidate in candidates.get(node) {
+                let p = position[candidate as usize];
+                if adjacent(p) { continue; }
+                // Put the node next to the candidate
+                for other in [(p + 1) % n, (p + n - 1) % n] {
+                    if other == at { continue; }
+                    f(Move::Swap { i: at.min(other), j: at.max(other) })?;
+                }
+            }
+        },
+        Neighbourhood::Insertion => {
+            for &candidate in candidates.get(node) {
+                let p = position[candidate as usize];
+                if adjacent(p) { continue; }
+                // Right after or right before the candidate, which moves back if it follows the node
+                let targets = if at < p { [p, p - 1] } else { [p + 1, p] };
+                for to in targets {
+                    if to == at || is_rotation(at, to, n) { continue; }
+                    f(Move::Insertion { from: at, to })?;
+                }
+            }
+        },
+        Neighbourhood::ThreeOpt => {
+            for &candidate in candidates.get(node) {
+                let p = position[candidate as usize];
+                if adjacent(p) { continue; }
+                for reconnection in RECONNECTIONS {
+                    let added = reconnection.added_edges();
+                    // The node at either end of every added edge, the candidate at the other
+                    for &(a, b) in added {
+                        for (end, other) in [(a, b), (b, a)] {
+                            let mut index = [0; 3];
+                            index[end.0] = (at + n - end.1) % n;
+                            index[other.0] = (p + n - other.1) % n;
+                            let free = 3 - end.0 - other.0;
+                            // A reconnection that keeps both edges of a cut is the same move wherever that cut is
+                            if !added.iter().any(|&(a, b)| a.0 == free || b.0 == free) {
+                                index[free] = match free {
+                                    0 => 0,
+                                    1 => index[0] + 1,
+                                    _ => index[1] + 1,
+                                };
+                                let [i, j, k] = index;
+                                if i < j && j < k && k < n {
+                                    f(Move::ThreeOpt { i, j, k, reconnection })?;
+                                }
+                                continue;
+                            }
+                            // Otherwise an added edge joins an end of the free cut to a candidate of its other end
+                            for &(a, b) in added {
+                                for (free_end, known) in [(a, b), (b, a)] {
+                                    if free_end.0 != free || known.0 == free { continue; }
+                                    let known_node = tour[(index[known.0] + known.1) % n];
+                                    for &candidate in candidates.get(known_node as usize) {
+                                        index[free] = (position[candidate as usize] + n - free_end.1) % n;
+                                        let [i, j, k] = index;
+                                        if i < j && j < k {
+                                            f(Move::ThreeOpt { i, j, k, reconnection })?;
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+    }
+    ControlFlow::Continue(())
+}
+
+/**
+ * Whether a target position is a valid place for the segment
+ * [start, start + length) modulo n, i.e. outside [start - 1, start + length).
+ */
+fn is_outside(start: usize, length: usize, target: usize, n: usize) -> bool {
+    (target + n + 1 - start) % n > length
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    const NEIGHBOURHOODS: [Neighbourhood; 5] = [Neighbourhood::TwoOpt, Neighbourhood::OrOpt, Neighbourhood::Swap, Neighbourhood::Insertion, Neighbourhood::ThreeOpt];
+
+    /**
+     * Apply a move to a copy of the tour and check it stays a permutation
+     * whose change of length is the delta of the move.
+     */
+    fn check_move(possible_move: Move, tour: &[u32], distance_matrix: &DistanceMatrix, symmetric: bool) {
+        let delta = possible_move.delta(distance_matrix, tour, symmetric);
+        let mut after = tour.to_vec();
+        possible_move.apply(&mut after);
+        let changed = possible_move.changed_positions(tour.len());
+        assert!((0..tour.len()).all(|p| changed.contains(&p) || after[p] == tour[p]), "{:?} changes other positions", possible_move);
+
+        let mut sorted = after.clone();
+        sorted.sort_unstable();
+        assert!(sorted.iter().enumerate().all(|(i, &node)| node as usize == i), "{:?} is not a permutation", possible_move);
+        let expected = utils::tour_length(&after, distance_matrix) - utils::tour_length(tour, distance_matrix);
+        assert!((delta as f64 - expected).abs() < 1e-3, "{:?}: delta {} instead of {}", possible_move, delta, expected);
+    }
+
+    /**
+     * Apply a move by cutting the tour into parts and gluing them back.
+     */
+    fn apply_by_copying(possible_move: Move, tour: &[u32]) -> Vec<u32> {
+        let n = tour.len();
+        let mut tour = tour.to_vec();
+        let possible_move = match possible_move {
+            // The same cycle with the segment at the start of the array
+            Move::OrOpt { start, length, target, reversed } if start + length > n => {
+                tour.rotate_left(start);
+                Move::OrOpt { start: 0, length, target: (target + n - start) % n, reversed }
+            },
+            _ => possible_move,
+        };
+        match possible_move {
+            Move::TwoOpt { i, j } => tour[i + 1..=j].reverse(),
+            Move::OrOpt { start, length, target, reversed } => {
+                let end = start + length - 1;
+                let mut segment: Vec<u32> = tour.drain(start..=end).collect();
+                if reversed {
+                    segment.reverse();
+                }
+                let target = if target > end { target - length } else { target };
+                tour.splice(target + 1..target + 1, segment);
+            },
+            Move::Swap { i, j } => tour.swap(i, j),
+            Move::Insertion { from, to } => {
+                let node = tour.remove(from);
+                tour.insert(to, node);
+            },
+            Move::ThreeOpt { i, j, k, reconnection } => {
+                let (reverse_first, reverse_second) = reconnection.reversed();
+                let mut first = tour[i + 1..=j].to_vec();
+                let mut second = tour[j + 1..=k].to_vec();
+                if reverse_first {
+                    first.reverse();
+                }
+                if reverse_second {
+                    second.reverse();
+                }
+                let middle = if reconnection.exchanges() { [second, first].concat() } else { [first, second].concat() };
+                tour[i + 1..=k].copy_from_slice(&middle);
+            },
+        }
+        tour
+    }
+
+    #[test]
+    fn apply_in_place_matches_copying() {
+        for n in [5, 6, 9] {
+            let tour: Vec<u32> = (0..n as u32).collect();
+            let _ = for_each_move(&NEIGHBOURHOODS, &tour, None, |possible_move| {
+                let mut after = tour.clone();
+                possible_move.apply(&mut after);
+                let expected = apply_by_copying(possible_move, &tour);
+                if possible_move.changed_positions(n).count() == n {
+                    assert_eq!(canonical(&after, false), canonical(&expected, false), "{:?}", possible_move);
+                } else {
+                    assert_eq!(after, expected, "{:?}", possible_move);
+                }
+                ControlFlow::<()>::Continue(())
+            });
+        }
+    }
+
+    #[test]
+    fn delta_matches_tour_length() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for symmetric in [true, false] {
+            for n in [5, 6, 9] {
+                let distance_matrix = utils::random_distance_matrix(n, symmetric, &mut rng);
+                for _ in 0..3 {
+                    let tour = utils::random_permutation(n, &mut rng);
+                    for neighbourhood in NEIGHBOURHOODS {
+                        let mut count = 0;
+                        let _ = for_each_move::<()>(&[neighbourhood], &tour, None, |possible_move| {
+                            check_move(possible_move, &tour, &distance_matrix, symmetric);
+                            count += 1;
+                            ControlFlow::Continue(())
+                        });
+                        assert!(count > 0, "{:?} has no moves", neighbourhood);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_reconnection_is_priced() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for symmetric in [true, false] {
+            let n = 12;
+            let distance_matrix = utils::random_distance_matrix(n, symmetric, &mut rng);
+            let tour = utils::random_permutation(n, &mut rng);
+            // Segments of one and several nodes, with the third edge closing the tour or not
+            for (i, j, k) in [(0, 1, 2), (0, 4, 11), (2, 5, 9), (3, 4, 10), (1, 7, 8)] {
+                for reconnection in RECONNECTIONS {
+                    check_move(Move::ThreeOpt { i, j, k, reconnection }, &tour, &distance_matrix, symmetric);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn touched_nodes_cover_every_changed_edge() {
+        for symmetric in [true, false] {
+            let n = 9;
+            let tour: Vec<u32> = (0..n as u32).collect();
+            let edges = |tour: &[u32]| -> HashSet<(u32, u32)> {
+                (0..n).map(|p| (tour[p], tour[(p + 1) % n])).flat_map(|(a, b)| if symmetric { vec![(a, b), (b, a)] } else { vec![(a, b)] }).collect()
+            };
+            let _ = for_each_move::<()>(&NEIGHBOURHOODS, &tour, None, |possible_move| {
+                let mut after = tour.clone();
+                possible_move.apply(&mut after);
+                let touched = possible_move.touched(&tour, symmetric);
+                for (a, b) in edges(&tour).symmetric_difference(&edges(&after)) {
+                    assert!(touched.contains(a) && touched.contains(b), "{:?} changes ({}, {}) without touching it", possible_move, a, b);
+                }
+                ControlFlow::Continue(())
+            });
+        }
+    }
+
+    #[test]
+    fn moves_at_a_node_are_valid() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for symmetric in [true, false] {
+            let n = 10;
+            let distance_matrix = utils::random_distance_matrix(n, symmetric, &mut rng);
+            let candidates = CandidateList::nearest(&distance_matrix, 3);
+            let tour = utils::random_permutation(n, &mut rng);
+            let position = utils::positions(&tour);
+            for neighbourhood in NEIGHBOURHOODS {
+                for at in 0..n {
+                    for candidates in [None, Some(&candidates)] {
+                        let _ = for_each_move_at::<()>(&[neighbourhood], &tour, &position, at, candidates, |possible_move| {
+                            check_move(possible_move, &tour, &distance_matrix, symmetric);
+                            ControlFlow::Continue(())
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn three_opt_moves_at_a_node_contain_it() {
+        let n = 9;
+        let tour: Vec<u32> = (0..n as u32).collect();
+        let position = utils::positions(&tour);
+        for at in 0..n {
+            let mut expected = Vec::new();
+            let _ = for_each_move::<()>(&[Neighbourhood::ThreeOpt], &tour, None, |possible_move| {
+                if let Move::ThreeOpt { i, j, k, .. } = possible_move {
+                    if i == at || j == at || k == at { expected.push(possible_move); }
+                }
+                ControlFlow::Continue(())
+            });
+            let mut browsed = Vec::new();
+            let _ = for_each_move_at::<()>(&[Neighbourhood::ThreeOpt], &tour, &position, at, None, |possible_move| {
+                browsed.push(possible_move);
+                ControlFlow::Continue(())
+            });
+            let key = |possible_move: &Move| format!("{:?}", possible_move);
+            expected.sort_by_key(key);
+            browsed.sort_by_key(key);
+            assert_eq!(browsed, expected);
+        }
+    }
+
+    #[test]
+    fn candidate_three_opt_at_a_node_does_not_grow_with_the_tour() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let (n, k) = (300, 5);
+        let distance_matrix = utils::random_distance_matrix(n, true, &mut rng);
+        let candidates = CandidateList::nearest(&distance_matrix, k);
+        let tour = utils::random_permutation(n, &mut rng);
+        let position = utils::positions(&tour);
+        for at in 0..n {
+            let mut browsed = 0;
+            let _ = for_each_move_at::<()>(&[Neighbourhood::ThreeOpt], &tour, &position, at, Some(&candidates), |_| {
+                browsed += 1;
+                ControlFlow::Continue(())
+            });
+            // Two candidate edges, one from the node, per reconnection, added edge and orientation
+            assert!(browsed <= RECONNECTIONS.len() * 6 * 4 * k * k, "{} moves at {}", browsed, at);
+        }
+    }
+
+    /**
+     * A tour from node 0 on, towards the smaller neighbour of node 0 if the instance is symmetric.
+     */
+    fn canonical(tour: &[u32], symmetric: bool) -> Vec<u32> {
+        let mut canonical = tour.to_vec();
+        let start = canonical.iter().position(|&node| node == 0).unwrap();
+        canonical.rotate_left(start);
+        if symmetric && canonical.len() > 2 && canonical[1] > canonical[canonical.len() - 1] {
+            canonical[1..].reverse();
+        }
+        canonical
+    }
+
+    /**
+     * The tours reached by the moves of a neighbourhood, other than the tour
+     * itself and the tour run backwards, which adds no edge.
+     */
+    fn reached(neighbourhood: Neighbourhood, tour: &[u32], candidates: Option<&CandidateList>, symmetric: bool) -> HashSet<Vec<u32>> {
+        let mut tours = HashSet::new();
+        let _ = for_each_move::<()>(&[neighbourhood], tour, candidates, |possible_move| {
+            let mut after = tour.to_vec();
+            possible_move.apply(&mut after);
+            tours.insert(canonical(&after, symmetric));
+            ControlFlow::Continue(())
+        });
+        let mut backwards = tour.to_vec();
+        backwards.reverse();
+        tours.remove(&canonical(tour, symmetric));
+        tours.remove(&canonical(&backwards, symmetric));
+        tours
+    }
+
+    #[test]
+    fn or_opt_segments_wrap_around_the_end() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let n = 8;
+        let tour = utils::random_permutation(n, &mut rng);
+        let distance_matrix = utils::random_distance_matrix(n, false, &mut rng);
+        let candidates = CandidateList::nearest(&distance_matrix, n - 1);
+        for candidates in [None, Some(&candidates)] {
+            let expected = reached(Neighbourhood::OrOpt, &tour, candidates, false);
+            // Where the array starts does not change the moves
+            for shift in 1..n {
+                let mut rotated = tour.clone();
+                rotated.rotate_left(shift);
+                assert_eq!(reached(Neighbourhood::OrOpt, &rotated, candidates, false), expected, "rotated by {}", shift);
+            }
+        }
+    }
+
+    #[test]
+    fn candidate_three_opt_reaches_every_move() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for symmetric in [true, false] {
+            let n = 9;
+            let distance_matrix = utils::random_distance_matrix(n, symmetric, &mut rng);
+            // Every other node is a candidate, so every new edge is a candidate edge
+            let candidates = CandidateList::nearest(&distance_matrix, n - 1);
+            for _ in 0..3 {
+                let tour = utils::random_permutation(n, &mut rng);
+                let restricted = reached(Neighbourhood::ThreeOpt, &tour, Some(&candidates), symmetric);
+                let full = reached(Neighbourhood::ThreeOpt, &tour, None, symmetric);
+                assert_eq!(restricted, full);
+            }
+        }
+    }
+}
@@ -1,4 +1,21 @@
 use crate::utils;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde_json::json;
+
+use crate::distance::DistanceMatrix;
+use crate::solver::{SolveResult, Solver};
+use crate::termination::Termination;
+use crate::tour::TourLength;
+
+/**
+ * The algorithm run by Random as a Solver.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RandomMethod {
+    Search,
+    Walk,
+}
 
 /**
     * Random struct
@@ -9,18 +26,28 @@ use crate::utils;
     *
     * @field distance_matrix: The distance matrix of the TSP problem
     * @field n: The number of nodes
+    * @field symmetric: Whether the distance matrix is symmetric
     * @field solution: The best solution found
     * @field distance: The distance of the best solution found
     * @field current_solution: The current solution
     * @field current_distance: The distance of the current solution
+    * @field method: The algorithm run by solve()
+    * @field termination: The stopping conditions, a budget is required
+    * @field seed: The seed of the random number generator
+    * @field rng: The random number generator
     */
 pub struct Random {
-    pub distance_matrix: Vec<Vec<f32>>,
+    pub distance_matrix: DistanceMatrix,
     pub n: usize,
+    pub symmetric: bool,
     solution: Vec<u32>,
     distance: f32,
     current_solution: Vec<u32>,
     current_distance: f32,
+    pub method: RandomMethod,
+    pub termination: Termination,
+    pub seed: u64,
+    rng: StdRng,
 }
 
 impl Random {
@@ -30,25 +57,56 @@ impl Random {
      * @param distance_matrix: The distance matrix of the TSP problem
      * @return: A new Random instance
      */
-    pub fn new(distance_matrix: Vec<Vec<f32>>) -> Random {
+    pub fn new(distance_matrix: DistanceMatrix) -> Random {
         let n = distance_matrix.len();
-        let solution = utils::random_permutation(n);
+        let mut rng = StdRng::seed_from_u64(0);
+        let solution = utils::random_permutation(n, &mut rng);
         let distance = utils::calculate_tour_distance(&solution, &distance_matrix).unwrap();
+        let symmetric = distance_matrix.is_symmetric();
         Random {
             distance_matrix,
             n,
+            symmetric,
             solution: solution.clone(),
-            distance: distance,
+            distance,
             current_solution: solution.clone(),
             current_distance: distance,
+            method: RandomMethod::Search,
+            termination: Termination::new().with_time_limit(1000.0),
+            seed: 0,
+            rng,
         }
     }
 
+    /**
+     * Set the algorithm run by solve()
+     *
+     * @param method: The algorithm
+     * @return: The Random instance
+     */
+    pub fn with_method(mut self, method: RandomMethod) -> Random {
+        self.method = method;
+        self
+    }
+
+    /**
+     * Initialize the solution from a given tour, a random one if None
+     *
+     * @param initial_tour: The starting tour
+     */
+    pub fn init_from(&mut self, initial_tour: Option<Vec<u32>>) -> Result<(), &'static str> {
+        self.solution = utils::initial_tour(initial_tour, self.n, &mut self.rng)?;
+        self.distance = utils::calculate_tour_distance(&self.solution, &self.distance_matrix).unwrap();
+        self.current_solution = self.solution.clone();
+        self.current_distance = self.distance;
+        Ok(())
+    }
+
     /**
      * Initialize a random solution
      */
     pub fn init_random(&mut self) {
-        self.solution = utils::random_permutation(self.n);
+        self.solution = utils::random_permutation(self.n, &mut self.rng);
         self.distance = utils::calculate_tour_distance(&self.solution, &self.distance_matrix).unwrap();
         self.current_solution = self.solution.clone();
         self.current_distance = self.distance;
@@ -58,52 +116,100 @@ impl Random {
     /**
      * Perform a Random Search on the TSP problem
      *
-     * @param time_limit_ms: The time limit in milliseconds
+     * Every sampled solution is an iteration.
+     *
      * @return: The best solution found and its distance
      */
-    pub fn search(&mut self, time_limit_ms: f64) -> Result<(Vec<u32>, f32, u32, u32), &'static str> {
-        let mut evaluated = 0;
+    pub fn search(&mut self) -> Result<SolveResult, &'static str> {
+        if self.termination.is_unbounded() {
+            return Err("Random search needs a time, evaluation or iteration budget");
+        }
+        let mut progress = self.termination.start();
+        progress.improve(self.distance);
 
-        let time_start = std::time::Instant::now();
-        while (time_start.elapsed().as_millis() as f64) < time_limit_ms {
-            self.current_solution = utils::random_permutation(self.n);
+        while !progress.is_done() {
+            self.current_solution = utils::random_permutation(self.n, &mut self.rng);
             self.current_distance = utils::calculate_tour_distance(&self.current_solution, &self.distance_matrix).unwrap();
-            evaluated += 1;
+            progress.evaluate();
             if self.current_distance < self.distance {
                 self.solution = self.current_solution.clone();
                 self.distance = self.current_distance;
             }
+            progress.iterate(self.distance);
         }
-        Ok((self.solution.clone(), self.distance, 0, evaluated))
+        Ok(SolveResult { tour: self.solution.clone(), distance: self.distance, steps: 0, evaluated: progress.evaluated, seed: self.seed })
     }
     
     /**
      * Perform a Random Walk search on the TSP problem
      *
-     * @param time_limit_ms: The time limit in milliseconds
+     * Every sampled move is an iteration.
+     *
      * @return: The best solution found and its distance
      */
-    pub fn walk(&mut self, time_limit_ms: f64) -> Result<(Vec<u32>, f32, u32, u32), &'static str> {
-        let mut evaluated = 0;
+    pub fn walk(&mut self) -> Result<SolveResult, &'static str> {
+        if self.termination.is_unbounded() {
+            return Err("Random walk needs a time, evaluation or iteration budget");
+        }
+        let mut length = TourLength::new(&self.solution, &self.distance_matrix);
+        let mut progress = self.termination.start();
+        progress.improve(length.get());
 
         let (mut i, mut j, mut delta, mut next_i, mut next_j);
-        let time_start = std::time::Instant::now();
-        while (time_start.elapsed().as_millis() as f64) < time_limit_ms {
-            (i, j) = utils::random_pair(self.n);
+        while !progress.is_done() {
+            (i, j) = utils::random_pair(self.n, &mut self.rng);
             if i > j { std::mem::swap(&mut i, &mut j); }
 
             next_i = (i + 1) % self.n;
             next_j = (j + 1) % self.n;
             if next_j == i { continue; }
 
-            delta = utils::get_delta_intra_route(&self.distance_matrix, self.solution[i], self.solution[next_i], self.solution[j], self.solution[next_j]);
-            evaluated += 1;
+            delta = utils::get_delta_2opt(&self.distance_matrix, &self.solution, i, j, self.symmetric);
+            progress.evaluate();
 
             if delta < 0.0 {
-                self.solution = utils::swap_2_edges(&self.solution, next_i, j, self.solution.clone());
-                self.distance = self.distance + delta;
+                self.solution[next_i..=j].reverse();
+                length.add(delta);
+                length.check(&self.solution, &self.distance_matrix);
             }
+            progress.iterate(length.get());
+        }
+        length.verify(&self.solution, &self.distance_matrix);
+        self.distance = length.get();
+        Ok(SolveResult { tour: self.solution.clone(), distance: self.distance, steps: 0, evaluated: progress.evaluated, seed: self.seed })
+    }
+}
+
+impl Solver for Random {
+    fn name(&self) -> &'static str {
+        match self.method {
+            RandomMethod::Search => "random_search",
+            RandomMethod::Walk => "random_walk",
+        }
+    }
+
+    fn metadata(&self) -> serde_json::Value {
+        json!({ "termination": self.termination })
+    }
+
+    fn set_termination(&mut self, termination: Termination) {
+        self.termination = termination;
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn solve_from(&mut self, initial_tour: Option<Vec<u32>>) -> Result<SolveResult, &'static str> {
+        // Every run starts from a fresh solution, the walk improves the supplied one
+        match self.method {
+            RandomMethod::Search => self.init_random(),
+            RandomMethod::Walk => self.init_from(initial_tour)?,
+        }
+        match self.method {
+            RandomMethod::Search => self.search(),
+            RandomMethod::Walk => self.walk(),
         }
-        Ok((self.solution.clone(), self.distance, 0, evaluated))
     }
 }
@@ -1,5 +1,16 @@
-use rand::Rng;
+use std::ops::ControlFlow;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use crate::utils;
+use serde_json::json;
+
+use crate::candidate_list::CandidateList;
+use crate::distance::DistanceMatrix;
+use crate::neighbourhood::{self, Neighbourhood};
+use crate::solver::{SolveResult, Solver};
+use crate::termination::Termination;
+use crate::tour::TourLength;
 
 /**
 * SimulatedAnnealing struct
@@ -10,12 +21,23 @@ use crate::utils;
 *
 * @field distance_matrix: The distance matrix of the TSP problem    
 * @field n: The number of nodes
+* @field symmetric: Whether the distance matrix is symmetric
+* @field neighbourhoods: The neighbourhoods browsed for a move to accept, in order
+* @field candidates: Restrict the search to the moves adding a candidate edge, all the moves if None
+* @field termination: Additional stopping conditions, the search always stops once the temperature drops to 0.001
+* @field seed: The seed of the random number generator
 */
 pub struct SimulatedAnnealing {
-    pub distance_matrix: Vec<Vec<f32>>,
+    pub distance_matrix: DistanceMatrix,
     pub n: usize,
+    pub symmetric: bool,
     temperature: f64,
     alpha: f64,
+    pub neighbourhoods: Vec<Neighbourhood>,
+    pub candidates: Option<CandidateList>,
+    pub termination: Termination,
+    pub seed: u64,
+    rng: StdRng,
 }
 
 impl SimulatedAnnealing {
@@ -25,16 +47,23 @@ impl SimulatedAnnealing {
     * @param distance_matrix: The distance matrix of the TSP problem
     * @return: A new SimulatedAnnealing instance
     */
-    pub fn new(distance_matrix: Vec<Vec<f32>>) -> SimulatedAnnealing {
+    pub fn new(distance_matrix: DistanceMatrix) -> SimulatedAnnealing {
         let n = distance_matrix.len();
+        let symmetric = distance_matrix.is_symmetric();
         let temperature = 0.0;
 
         let alpha = 0.99;
         SimulatedAnnealing {
             distance_matrix,
             n,
+            symmetric,
             temperature,
             alpha,
+            neighbourhoods: vec![Neighbourhood::TwoOpt],
+            candidates: None,
+            termination: Termination::new(),
+            seed: 0,
+            rng: StdRng::seed_from_u64(0),
         }
     }
 
@@ -42,23 +71,21 @@ impl SimulatedAnnealing {
     * Determine initial temperature.
     */ 
     pub fn determine_initial_temperature(&mut self) {
-        let current_tour = utils::random_permutation(self.n);
+        let current_tour = utils::random_permutation(self.n, &mut self.rng);
 
-        let mut rng = rand::thread_rng();
-        let sample_n = rng.gen_range(0..self.n / 2);
+        let sample_n = self.rng.gen_range(0..self.n / 2);
 
         let mut n_samples = 0;
         let mut avg_pos_delta: f64 = 0.0;
 
         // Get a sample of edges
         for i in 0..sample_n {
-            let next_i = i + 1;
             for j in i + 2..self.n {
                 let next_j = (j + 1) % self.n;
                 // Skip directly proceeding edge
                 if next_j == i { continue; }
                 // Calculated delta fitness
-                let delta = utils::get_delta_intra_route(&self.distance_matrix, current_tour[i], current_tour[next_i], current_tour[j], current_tour[next_j]);
+                let delta = utils::get_delta_2opt(&self.distance_matrix, &current_tour, i, j, self.symmetric);
                 if delta > 0.0 {
                     avg_pos_delta += delta as f64;
                     n_samples += 1;
@@ -75,47 +102,67 @@ impl SimulatedAnnealing {
     /**
     * Perform a Simulated Annealing on the TSP problem
     *
+    * @param initial_tour: The starting tour, a random one if None
     * @return: The best solution found and its distance
     */
-    pub fn run(&self) -> Result<(Vec<u32>, f32, u32, u32), &'static str> {
+    pub fn run(&mut self, initial_tour: Option<Vec<u32>>) -> Result<SolveResult, &'static str> {
         let mut current_temperature = self.temperature;
-        let mut current_tour = utils::random_permutation(self.n);
-        let mut best_tour = current_tour.clone();
+        let mut current_tour = utils::initial_tour(initial_tour, self.n, &mut self.rng)?;
+        let mut length = TourLength::new(&current_tour, &self.distance_matrix);
 
-        let mut evaluated = 0;
+        let mut progress = self.termination.start();
+        progress.improve(length.get());
         let mut steps = 0;
-//        let mut no_improvement = 0;
-
-        while current_temperature > 0.001 {
-            let mut accept = false;
-            // Intra-route neighbourhood: Iterate all distinct 2-edge pairs
-            for i in 0..self.n {
-                let next_i = (i + 1) % self.n;
-                for j in i + 2..self.n {
-                    let next_j = (j + 1) % self.n;
-                    // Skip directly proceeding edge
-                    if next_j == i { continue; }
-                    // Calculated delta fitness
-                    let delta = utils::get_delta_intra_route(&self.distance_matrix,current_tour[i], current_tour[next_i], current_tour[j], current_tour[next_j]);
-                    evaluated += 1;
-
-                    if delta < 0.0 || f64::exp(-delta as f64 / current_temperature) > rand::random() {
-                        best_tour = utils::swap_2_edges(&current_tour, next_i, j, best_tour);
-                        accept = true;
-                        break;
-                    }
-                }
-                if accept {
-                    current_tour = best_tour.clone();
-                    steps += 1;
-                    break;
+
+        // Every temperature level is an iteration
+        while current_temperature > 0.001 && !progress.is_done() {
+            // Take the first move passing the Metropolis criterion
+            let accepted = neighbourhood::for_each_move(&self.neighbourhoods, &current_tour, self.candidates.as_ref(), |possible_move| {
+                if progress.is_done() { return ControlFlow::Break(None); }
+                // Calculated delta fitness
+                let delta = possible_move.delta(&self.distance_matrix, &current_tour, self.symmetric);
+                progress.evaluate();
+
+                if delta < 0.0 || f64::exp(-delta as f64 / current_temperature) > self.rng.gen::<f64>() {
+                    return ControlFlow::Break(Some((possible_move, delta)));
                 }
+                ControlFlow::Continue(())
+            });
+            if let ControlFlow::Break(Some((accepted_move, delta))) = accepted {
+                accepted_move.apply(&mut current_tour);
+                length.add(delta);
+                length.check(&current_tour, &self.distance_matrix);
+                steps += 1;
             }
             // Exponential decay
             current_temperature = current_temperature / (1.0 + self.alpha * current_temperature);
+            progress.iterate(length.get());
         }
-        
-        let distance = utils::calculate_tour_distance(&current_tour, &self.distance_matrix).unwrap();
-        Ok((current_tour, distance, steps, evaluated))
+
+        length.verify(&current_tour, &self.distance_matrix);
+        Ok(SolveResult { tour: current_tour, distance: length.get(), steps, evaluated: progress.evaluated, seed: self.seed })
+    }
+}
+
+impl Solver for SimulatedAnnealing {
+    fn name(&self) -> &'static str {
+        "simulated_annealing"
+    }
+
+    fn metadata(&self) -> serde_json::Value {
+        json!({ "initial_temperature": self.temperature, "alpha": self.alpha, "neighbourhoods": self.neighbourhoods, "candidates": self.candidates.as_ref().map(CandidateList::k), "termination": self.termination })
+    }
+
+    fn set_termination(&mut self, termination: Termination) {
+        self.termination = termination;
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn solve_from(&mut self, initial_tour: Option<Vec<u32>>) -> Result<SolveResult, &'static str> {
+        self.run(initial_tour)
     }
 }
@@ -0,0 +1,211 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use crate::construction::{Construction, ConstructionMethod};
+use crate::exact::{Exact, ExactMethod};
+use crate::instance::Instance;
+use crate::iterated_local_search::IteratedLocalSearch;
+use crate::lin_kernighan::LinKernighan;
+use crate::local_search::{LocalSearch, LocalSearchMethod};
+use crate::random::{Random, RandomMethod};
+use crate::simulated_annealing::SimulatedAnnealing;
+use crate::tabu_search::TabuSearch;
+use crate::termination::Termination;
+
+
+/**
+ * SolveResult struct
+ *
+ * The outcome of a single run of an algorithm.
+ *
+ * @field tour: The best tour found
+ * @field distance: The length of the best tour found
+ * @field steps: The number of accepted moves
+ * @field evaluated: The number of evaluated solutions
+ * @field seed: The seed of the random number generator the run started with
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SolveResult {
+    pub tour: Vec<u32>,
+    pub distance: f32,
+    pub steps: u32,
+    pub evaluated: u32,
+    pub seed: u64,
+}
+
+/**
+ * Solver trait
+ *
+ * Common interface of every algorithm, used by the experiment runners.
+ */
+pub trait Solver {
+    /**
+     * Name of the algorithm, also used as the name of its results file.
+     */
+    fn name(&self) -> &'static str;
+
+    /**
+     * Parameters of the algorithm, saved next to its results.
+     */
+    fn metadata(&self) -> Value {
+        Value::Object(serde_json::Map::new())
+    }
+
+    /**
+     * Set the stopping conditions of the following runs. Construction
+     * methods ignore them.
+     *
+     * @param termination: The stopping conditions
+     */
+    fn set_termination(&mut self, _termination: Termination) {}
+
+    /**
+     * Reseed the random number generator. Replaying a seed gives the same
+     * tour; a run stopped by a time limit is replayed with
+     * Termination::replay and the number of solutions it evaluated.
+     *
+     * @param seed: The seed
+     */
+    fn set_seed(&mut self, seed: u64);
+
+    /**
+     * Perform a single run of the algorithm from a random starting tour.
+     *
+     * @return: The best solution found
+     */
+    fn solve(&mut self) -> Result<SolveResult, &'static str> {
+        self.solve_from(None)
+    }
+
+    /**
+     * Perform a single run of the algorithm. Improvement methods start from
+     * the supplied tour, construction methods ignore it.
+     *
+     * @param initial_tour: The starting tour, a random one if None
+     * @return: The best solution found
+     */
+    fn solve_from(&mut self, initial_tour: Option<Vec<u32>>) -> Result<SolveResult, &'static str>;
+}
+
+/**
+ * Names of all the algorithms, in the order the experiments run them.
+ */
+pub const ALGORITHMS: [&str; 20] = ["greedy", "steepest", "lin_kernighan", "random_search", "random_walk", "heuristic", "nearest_neighbour_all_starts", "grasp", "nearest_neighbour_kd_tree", "nearest_insertion", "farthest_insertion", "cheapest_insertion", "random_insertion", "greedy_edge", "savings", "mst_doubling", "christofides_greedy_matching", "simulated_annealing", "tabu_search", "iterated_local_search"];
+
+/**
+ * Names of the algorithms that only stop on a budget, the runners give them
+ * a time limit. The others run until they stop on their own, e.g. in a
+ * local optimum.
+ */
+pub const BUDGETED: [&str; 3] = ["random_search", "random_walk", "iterated_local_search"];
+
+/**
+ * Create an algorithm by its name.
+ *
+ * @param name: The name of the algorithm
+ * @param instance: The TSP problem
+ * @return: The solver, or None if the name is unknown or the algorithm needs a metric the instance does not have
+ */
+pub fn create_solver(name: &str, instance: &Instance) -> Option<Box<dyn Solver>> {
+    let distance_matrix = &instance.distance_matrix;
+    let solver: Box<dyn Solver> = match name {
+        "greedy" => Box::new(LocalSearch::new(distance_matrix.clone()).with_method(LocalSearchMethod::Greedy)),
+        "steepest" => Box::new(LocalSearch::new(distance_matrix.clone()).with_method(LocalSearchMethod::Steepest)),
+        "lin_kernighan" => Box::new(LinKernighan::new(distance_matrix.clone())),
+        "heuristic" => Box::new(LocalSearch::new(distance_matrix.clone()).with_method(LocalSearchMethod::Heuristic)),
+        "nearest_neighbour" => Box::new(Construction::new(distance_matrix.clone()).with_method(ConstructionMethod::NearestNeighbour)),
+        "nearest_neighbour_all_starts" => Box::new(Construction::new(distance_matrix.clone()).with_method(ConstructionMethod::NearestNeighbourAllStarts)),
+        "grasp" => Box::new(Construction::new(distance_matrix.clone()).with_method(ConstructionMethod::Grasp)),
+        "nearest_neighbour_kd_tree" => {
+            // The tree searches the planar Euclidean distance, other metrics would pick other cities
+            if !instance.metric.is_some_and(|metric| metric.is_planar_euclidean()) { return None; }
+            Box::new(Construction::new(distance_matrix.clone())
+                .with_method(ConstructionMethod::NearestNeighbourKdTree)
+                .with_coordinates(instance.coordinates.clone()))
+        },
+        "nearest_insertion" => Box::new(Construction::new(distance_matrix.clone()).with_method(ConstructionMethod::NearestInsertion)),
+        "farthest_insertion" => Box::new(Construction::new(distance_matrix.clone()).with_method(ConstructionMethod::FarthestInsertion)),
+        "cheapest_insertion" => Box::new(Construction::new(distance_matrix.clone()).with_method(ConstructionMethod::CheapestInsertion)),
+        "random_insertion" => Box::new(Construction::new(distance_matrix.clone()).with_method(ConstructionMethod::RandomInsertion)),
+        "greedy_edge" => Box::new(Construction::new(distance_matrix.clone()).with_method(ConstructionMethod::GreedyEdge)),
+        "savings" => Box::new(Construction::new(distance_matrix.clone()).with_method(ConstructionMethod::Savings)),
+        "mst_doubling" => Box::new(Construction::new(distance_matrix.clone()).with_method(ConstructionMethod::MstDoubling)),
+        "christofides_greedy_matching" => Box::new(Construction::new(distance_matrix.clone()).with_method(ConstructionMethod::ChristofidesGreedyMatching)),
+        "dynamic_programming" => Box::new(Exact::new(distance_matrix.clone()).with_method(ExactMethod::DynamicProgramming)),
+        "branch_and_bound" => Box::new(Exact::new(distance_matrix.clone()).with_method(ExactMethod::BranchAndBound)),
+        "random_search" => Box::new(Random::new(distance_matrix.clone()).with_method(RandomMethod::Search)),
+        "random_walk" => Box::new(Random::new(distance_matrix.clone()).with_method(RandomMethod::Walk)),
+        "simulated_annealing" => {
+            let mut solver = SimulatedAnnealing::new(distance_matrix.clone());
+            solver.determine_initial_temperature();
+            Box::new(solver)
+        },
+        "tabu_search" => Box::new(TabuSearch::new(distance_matrix.clone(), None)),
+        "iterated_local_search" => Box::new(IteratedLocalSearch::new(distance_matrix.clone())),
+        _ => return None,
+    };
+    Some(solver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_algorithm_builds_a_tour() {
+        let instance = Instance::from_file("data/berlin52.txt").unwrap();
+        for name in ALGORITHMS {
+            let mut solver = create_solver(name, &instance).unwrap();
+            assert_eq!(solver.name(), name);
+            solver.set_termination(Termination::new().with_max_evaluations(20_000));
+            solver.set_seed(1);
+            let result = solver.solve().unwrap();
+
+            let mut sorted = result.tour.clone();
+            sorted.sort_unstable();
+            assert!(sorted.iter().enumerate().all(|(i, &node)| node as usize == i), "{} does not build a permutation", name);
+            let distance = crate::utils::calculate_tour_distance(&result.tour, &instance.distance_matrix).unwrap();
+            assert!((result.distance - distance).abs() < 1e-2, "{} reports {} for a tour of {}", name, result.distance, distance);
+        }
+    }
+
+    #[test]
+    fn timed_runs_replay_from_their_seed_and_evaluations() {
+        let instance = Instance::from_file("data/berlin52.txt").unwrap();
+        for name in ALGORITHMS {
+            for (seed, time_limit_ms) in [(1, 1.0), (2, 10.0)] {
+                let termination = Termination::new().with_time_limit(time_limit_ms);
+                let mut solver = create_solver(name, &instance).unwrap();
+                solver.set_termination(termination.clone());
+                solver.set_seed(seed);
+                let timed = solver.solve().unwrap();
+
+                let mut solver = create_solver(name, &instance).unwrap();
+                solver.set_termination(termination.replay(timed.evaluated));
+                solver.set_seed(seed);
+                let replayed = solver.solve().unwrap();
+                assert_eq!(replayed.tour, timed.tour, "{} does not replay seed {}", name, seed);
+                assert_eq!(replayed.evaluated, timed.evaluated, "{} does not replay seed {}", name, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn kd_tree_needs_a_planar_metric() {
+        let text = "NAME: geo\nTYPE: TSP\nDIMENSION: 4\nEDGE_WEIGHT_TYPE: GEO\nNODE_COORD_SECTION\n1 85.0 0.0\n2 85.0 180.0\n3 70.0 0.0\n4 0.0 0.0\nEOF\n";
+        let instance = Instance::parse(text.as_bytes()).unwrap();
+        assert!(create_solver("nearest_neighbour_kd_tree", &instance).is_none());
+        assert!(create_solver("nearest_neighbour", &instance).is_some());
+    }
+
+    #[test]
+    fn only_budgeted_algorithms_need_a_budget() {
+        let instance = Instance::from_file("data/berlin52.txt").unwrap();
+        for name in ALGORITHMS {
+            let mut solver = create_solver(name, &instance).unwrap();
+            solver.set_termination(Termination::new());
+            solver.set_seed(1);
+            assert_eq!(solver.solve().is_err(), BUDGETED.contains(&name), "{}", name);
+        }
+    }
+}
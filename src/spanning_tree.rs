@@ -0,0 +1,128 @@
+use crate::distance::DistanceMatrix;
+
+/**
+ * SpanningTree struct
+ *
+ * A spanning tree over the cities, rooted at one of them.
+ *
+ * @field root: The root of the tree
+ * @field parent: The parent of every city, the root is its own parent
+ * @field weight: The sum of the weights of the edges
+ */
+#[derive(Debug, Clone)]
+pub struct SpanningTree {
+    pub root: usize,
+    pub parent: Vec<usize>,
+    pub weight: f64,
+}
+
+impl SpanningTree {
+    /**
+     * Build a minimum spanning tree over the distance matrix. An edge weighs
+     * the shorter of its two directions, so the tree is also defined for an
+     * asymmetric instance.
+     *
+     * @param distance_matrix: The distance matrix of the TSP problem
+     * @param root: The root of the tree
+     * @return: The tree
+     */
+    pub fn minimum(distance_matrix: &DistanceMatrix, root: usize) -> SpanningTree {
+        let symmetric = distance_matrix.is_symmetric();
+        SpanningTree::prim(distance_matrix.len(), root, |a, b| {
+            if symmetric {
+                distance_matrix.get(a, b) as f64
+            } else {
+                distance_matrix.get(a, b).min(distance_matrix.get(b, a)) as f64
+            }
+        })
+    }
+
+    /**
+     * Build a minimum spanning tree of a complete graph with Prim's
+     * algorithm, which takes O(n²) and looks at every edge once.
+     *
+     * @param n: The number of cities
+     * @param root: The root of the tree
+     * @param weight: The weight of the edge between two cities
+     * @return: The tree
+     */
+    pub fn prim(n: usize, root: usize, weight: impl Fn(usize, usize) -> f64) -> SpanningTree {
+        let mut parent = vec![root; n];
+        let mut in_tree = vec![false; n];
+        // The lightest edge from every city outside to the tree
+        let mut lightest = vec![f64::INFINITY; n];
+        let mut total = 0.0;
+
+        let mut city = root;
+        in_tree[root] = true;
+        for _ in 1..n {
+            let mut next = usize::MAX;
+            for other in 0..n {
+                if in_tree[other] { continue; }
+                let edge = weight(city, other);
+                if edge < lightest[other] {
+                    lightest[other] = edge;
+                    parent[other] = city;
+                }
+                if next == usize::MAX || lightest[other] < lightest[next] {
+                    next = other;
+                }
+            }
+            in_tree[next] = true;
+            total += lightest[next];
+            city = next;
+        }
+        SpanningTree { root, parent, weight: total }
+    }
+
+    /**
+     * The number of cities.
+     */
+    pub fn len(&self) -> usize {
+        self.parent.len()
+    }
+
+    /**
+     * Whether the tree has no cities.
+     */
+    pub fn is_empty(&self) -> bool {
+        self.parent.is_empty()
+    }
+
+    /**
+     * The edges of the tree, as (parent, child) pairs.
+     */
+    pub fn edges(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.parent.iter().enumerate().filter(|&(child, _)| child != self.root).map(|(child, &parent)| (parent, child))
+    }
+
+    /**
+     * The number of edges at every city.
+     */
+    pub fn degrees(&self) -> Vec<usize> {
+        let mut degrees = vec![0; self.len()];
+        for (parent, child) in self.edges() {
+            degrees[parent] += 1;
+            degrees[child] += 1;
+        }
+        degrees
+    }
+
+    /**
+     * The cities in depth-first preorder from the root, children by increasing index.
+     */
+    pub fn preorder(&self) -> Vec<u32> {
+        let mut children = vec![Vec::new(); self.len()];
+        for (parent, child) in self.edges() {
+            children[parent].push(child);
+        }
+        let mut order = Vec::with_capacity(self.len());
+        if self.is_empty() { return order; }
+        let mut stack = vec![self.root];
+        while let Some(city) = stack.pop() {
+            order.push(city as u32);
+            stack.extend(children[city].iter().rev());
+        }
+        order
+    }
+}
@@ -1,4 +1,16 @@
+use std::ops::ControlFlow;
+
 use crate::utils;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde_json::json;
+
+use crate::candidate_list::CandidateList;
+use crate::distance::DistanceMatrix;
+use crate::neighbourhood::{self, Move, Neighbourhood};
+use crate::solver::{SolveResult, Solver};
+use crate::termination::Termination;
+use crate::tour::TourLength;
 
 /**
  * TabuSearch structure
@@ -9,15 +21,26 @@ use crate::utils;
  *
  * @field distance_matrix: The distance matrix of the TSP problem
  * @field n: The number of nodes
+ * @field symmetric: Whether the distance matrix is symmetric
+ * @field neighbourhoods: The neighbourhoods the elite moves are drawn from
+ * @field candidates: Restrict the elite moves to the ones adding a candidate edge, all the moves if None
+ * @field termination: Additional stopping conditions, the search always stops after max_iter iterations without improvement
+ * @field seed: The seed of the random number generator
  */
 pub struct TabuSearch {
-    pub distance_matrix: Vec<Vec<f32>>,
+    pub distance_matrix: DistanceMatrix,
     pub n: usize,
+    pub symmetric: bool,
     tabu_list: Vec<Vec<usize>>,
     tabu_tenure: usize,
-    tabu_elite_moves: Vec<(usize, usize, f32)>,
+    tabu_elite_moves: Vec<(Move, f32)>,
     max_iter: u32,
     max_moves: usize,
+    pub neighbourhoods: Vec<Neighbourhood>,
+    pub candidates: Option<CandidateList>,
+    pub termination: Termination,
+    pub seed: u64,
+    rng: StdRng,
 }
 
 impl TabuSearch {
@@ -29,8 +52,9 @@ impl TabuSearch {
      * after which the search will stop. Default is 1000.
      * @return: A new TabuSearch instance.
      */
-    pub fn new(distance_matrix: Vec<Vec<f32>>, iters: Option<u32>) -> TabuSearch {
+    pub fn new(distance_matrix: DistanceMatrix, iters: Option<u32>) -> TabuSearch {
         let n = distance_matrix.len();
+        let symmetric = distance_matrix.is_symmetric();
         // Tabu tenure is set to integer representing size of the problem divided by 4
         let tabu_tenure = n / 4;
         let max_iter: u32 = iters.unwrap_or(100);
@@ -38,111 +62,176 @@ impl TabuSearch {
         TabuSearch {
             distance_matrix,
             n,
+            symmetric,
             tabu_list: vec![vec![0; n]; n],
             tabu_tenure,
             tabu_elite_moves: Vec::new(),
             max_iter,
             max_moves,
+            neighbourhoods: vec![Neighbourhood::TwoOpt],
+            candidates: None,
+            termination: Termination::new(),
+            seed: 0,
+            rng: StdRng::seed_from_u64(0),
         }
     }
 
     /**
      * Perform a Tabu Search on the TSP problem.
      *
+     * @param initial_tour: The starting tour, a random one if None.
      * @return: The best solution found and its distance.
      */
-    pub fn run(&mut self) -> Result<(Vec<u32>, f32, u32, u32), &'static str> {
-        let mut current_tour = utils::random_permutation(self.n);
+    pub fn run(&mut self, initial_tour: Option<Vec<u32>>) -> Result<SolveResult, &'static str> {
+        let mut current_tour = utils::initial_tour(initial_tour, self.n, &mut self.rng)?;
         let mut best_tour = current_tour.clone();
-        let mut best_distance = utils::calculate_tour_distance(&best_tour, &self.distance_matrix).unwrap();
-        let mut current_distance = best_distance;
+        let mut length = TourLength::new(&current_tour, &self.distance_matrix);
+        let mut best_length = length;
         let mut best_iter: u32 = 0;
         let mut iter: u32 = 0;
         let mut tabu_list = self.tabu_list.clone();
-        let mut evaluated: u32 = 0;
         self.tabu_elite_moves.clear();
 
-        while iter - best_iter < self.max_iter {
+        let mut progress = self.termination.start();
+        progress.improve(best_length.get());
+
+        while iter - best_iter < self.max_iter && !progress.is_done() {
 
             iter += 1;
 
-            // Generate all possible 2-opt moves, evaluate their delta and sort them
+            // Update tabu list
+            for (i, row) in tabu_list.iter_mut().enumerate() {
+                for tenure in row.iter_mut().skip(i + 1) {
+                    if *tenure > 0 {
+                        *tenure -= 1;
+                    }
+                }
+            }
+
+            // Evaluate all possible moves, keep the k best ones and sort them
             // in ascending order of delta
             if self.tabu_elite_moves.is_empty() {
-                for i in 0..self.n {
-                    for j in i + 1..self.n {
-                        let next_i = (i + 1) % self.n;
-                        let next_j = (j + 1) % self.n;
-                        if next_j == i { continue; }
-
-                        // Update tabu list
-                        if tabu_list[i][j] > 0 {
-                            tabu_list[i][j] -= 1;
-                        }
+                let elite_moves = &mut self.tabu_elite_moves;
+                let max_moves = self.max_moves;
+                let _ = neighbourhood::for_each_move(&self.neighbourhoods, &current_tour, self.candidates.as_ref(), |possible_move| {
+                    if progress.is_done() { return ControlFlow::Break(()); }
 
-                        let delta = utils::get_delta_intra_route(&self.distance_matrix, current_tour[i], current_tour[next_i], current_tour[j], current_tour[next_j]);
-                        self.tabu_elite_moves.push((i, j, delta));
-
-                        evaluated += 1;
+                    let delta = possible_move.delta(&self.distance_matrix, &current_tour, self.symmetric);
+                    elite_moves.push((possible_move, delta));
+                    // The buffer never holds more than twice the moves kept
+                    if elite_moves.len() >= 2 * max_moves.max(1) {
+                        keep_best(elite_moves, max_moves);
                     }
-                }
-                self.tabu_elite_moves.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap());
 
-                // Leave only k best moves
-                if self.tabu_elite_moves.len() > self.max_moves {
-                    self.tabu_elite_moves.truncate(self.max_moves);
-                }
+                    progress.evaluate();
+                    ControlFlow::Continue(())
+                });
+                keep_best(&mut self.tabu_elite_moves, self.max_moves);
+                self.tabu_elite_moves.sort_by(|a, b| a.1.total_cmp(&b.1));
             }
             else {
                 // Re-calculating delta for each move
                 for tabu_move in self.tabu_elite_moves.iter_mut() {
-                    let i = tabu_move.0;
-                    let j = tabu_move.1;
-                    let next_i = (i + 1) % self.n;
-                    let next_j = (j + 1) % self.n;
-                    tabu_move.2 = utils::get_delta_intra_route(&self.distance_matrix, current_tour[i], current_tour[next_i], current_tour[j], current_tour[next_j]);
-                }
-                self.tabu_elite_moves.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap());
-
-                // Update tabu list
-                for i in 0..self.n {
-                    for j in i + 1..self.n {
-                        if tabu_list[i][j] > 0 {
-                            tabu_list[i][j] -= 1;
-                        }
-                    }
+                    tabu_move.1 = tabu_move.0.delta(&self.distance_matrix, &current_tour, self.symmetric);
+                    progress.evaluate();
                 }
+                self.tabu_elite_moves.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
 
                 // If the best move is better than the current solution by a margin of 0.5%
-                if self.tabu_elite_moves[0].2 / current_distance > -0.005 {
+                if self.tabu_elite_moves[0].1 / length.get() > -0.005 {
                     self.tabu_elite_moves.clear();
+                    progress.iterate(best_length.get());
                     continue;
                 }
             }
 
             // Take the first non-tabu move
             for i in 0..self.tabu_elite_moves.len() {
-                let possible_move = self.tabu_elite_moves[i];
+                let (possible_move, delta) = self.tabu_elite_moves[i];
+                // Keyed by two positions, so 3-opt moves are tabu by their outer cuts
+                let (a, b) = possible_move.key();
                 // If not tabu or move gives better solution than best solution found so far
-                if tabu_list[possible_move.0][possible_move.1] == 0 ||
-                   current_distance + possible_move.2 < best_distance
+                if tabu_list[a][b] == 0 ||
+                   length.get() + delta < best_length.get()
                 {
-                    let next_i = (possible_move.0 + 1) % self.n;
-                    current_tour = utils::swap_2_edges(&current_tour, next_i, possible_move.1, best_tour.clone());
-                    current_distance += possible_move.2;
-                    tabu_list[possible_move.0][possible_move.1] = self.tabu_tenure;
+                    possible_move.apply(&mut current_tour);
+                    length.add(delta);
+                    length.check(&current_tour, &self.distance_matrix);
+                    tabu_list[a][b] = self.tabu_tenure;
                     self.tabu_elite_moves.remove(i);
                     break;
                 }
             }
 
-            if current_distance < best_distance {
+            if length.get() < best_length.get() {
                 best_tour = current_tour.clone();
-                best_distance = current_distance;
+                best_length = length;
                 best_iter = iter;
             }
+            progress.iterate(best_length.get());
         }
 
-        Ok((best_tour, best_distance, best_iter, evaluated))
+        best_length.verify(&best_tour, &self.distance_matrix);
+        Ok(SolveResult { tour: best_tour, distance: best_length.get(), steps: best_iter, evaluated: progress.evaluated, seed: self.seed })
+    }
+}
+
+/**
+ * Keep the k moves with the lowest delta, in no particular order.
+ *
+ * @param moves: The moves and their delta
+ * @param k: The number of moves to keep
+ */
+fn keep_best(moves: &mut Vec<(Move, f32)>, k: usize) {
+    if moves.len() <= k { return; }
+    if k > 0 {
+        moves.select_nth_unstable_by(k - 1, |a, b| a.1.total_cmp(&b.1));
+    }
+    moves.truncate(k);
+}
+
+impl Solver for TabuSearch {
+    fn name(&self) -> &'static str {
+        "tabu_search"
+    }
+
+    fn metadata(&self) -> serde_json::Value {
+        json!({ "tabu_tenure": self.tabu_tenure, "max_iter": self.max_iter, "max_moves": self.max_moves, "neighbourhoods": self.neighbourhoods, "candidates": self.candidates.as_ref().map(CandidateList::k), "termination": self.termination })
+    }
+
+    fn set_termination(&mut self, termination: Termination) {
+        self.termination = termination;
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn solve_from(&mut self, initial_tour: Option<Vec<u32>>) -> Result<SolveResult, &'static str> {
+        self.run(initial_tour)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn keep_best_keeps_the_lowest_deltas() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for k in [0, 1, 5, 40] {
+            let mut moves: Vec<(Move, f32)> = (0..30).map(|i| (Move::Swap { i, j: i + 1 }, rng.gen_range(-50..50) as f32)).collect();
+            let mut expected: Vec<f32> = moves.iter().map(|&(_, delta)| delta).collect();
+            expected.sort_by(f32::total_cmp);
+            expected.truncate(k);
+
+            keep_best(&mut moves, k);
+            let mut kept: Vec<f32> = moves.iter().map(|&(_, delta)| delta).collect();
+            kept.sort_by(f32::total_cmp);
+            assert_eq!(kept, expected);
+        }
     }
 }
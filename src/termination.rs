@@ -0,0 +1,262 @@
+use std::time::Instant;
+
+use serde::{Serialize, Deserialize};
+
+
+/**
+ * Termination struct
+ *
+ * Stopping conditions shared by all the solvers. A run stops as soon as any
+ * of the set conditions holds, or when the algorithm stops on its own
+ * (e.g. in a local optimum). Unset conditions are ignored.
+ *
+ * @field time_limit_ms: Wall-clock budget in milliseconds
+ * @field max_evaluations: Budget of evaluated solutions
+ * @field max_iterations: Budget of iterations (accepted moves or restarts, depending on the algorithm)
+ * @field max_no_improvement: Iterations in a row without improving the best solution
+ * @field target_distance: Stop once a solution at least this good is found, e.g. the known optimum
+ */
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Termination {
+    pub time_limit_ms: Option<f64>,
+    pub max_evaluations: Option<u32>,
+    pub max_iterations: Option<u32>,
+    pub max_no_improvement: Option<u32>,
+    pub target_distance: Option<f32>,
+}
+
+impl Termination {
+    /**
+     * Create a Termination without any condition.
+     *
+     * @return: A new Termination instance
+     */
+    pub fn new() -> Termination {
+        Termination::default()
+    }
+
+    /**
+     * Set the wall-clock budget.
+     *
+     * @param time_limit_ms: The time limit in milliseconds
+     * @return: The Termination instance
+     */
+    pub fn with_time_limit(mut self, time_limit_ms: f64) -> Termination {
+        self.time_limit_ms = Some(time_limit_ms);
+        self
+    }
+
+    /**
+     * Set the budget of evaluated solutions.
+     *
+     * @param max_evaluations: The maximal number of evaluations
+     * @return: The Termination instance
+     */
+    pub fn with_max_evaluations(mut self, max_evaluations: u32) -> Termination {
+        self.max_evaluations = Some(max_evaluations);
+        self
+    }
+
+    /**
+     * Set the budget of iterations.
+     *
+     * @param max_iterations: The maximal number of iterations
+     * @return: The Termination instance
+     */
+    pub fn with_max_iterations(mut self, max_iterations: u32) -> Termination {
+        self.max_iterations = Some(max_iterations);
+        self
+    }
+
+    /**
+     * Set the number of iterations without improvement after which to stop.
+     *
+     * @param max_no_improvement: The maximal number of iterations without improvement
+     * @return: The Termination instance
+     */
+    pub fn with_max_no_improvement(mut self, max_no_improvement: u32) -> Termination {
+        self.max_no_improvement = Some(max_no_improvement);
+        self
+    }
+
+    /**
+     * Set the distance at which to stop.
+     *
+     * @param target_distance: The target tour length
+     * @return: The Termination instance
+     */
+    pub fn with_target_distance(mut self, target_distance: f32) -> Termination {
+        self.target_distance = Some(target_distance);
+        self
+    }
+
+    /**
+     * Whether a run could go on forever without any of the conditions,
+     * i.e. none of them bounds the run by itself.
+     *
+     * @return: True if no time, evaluation or iteration budget is set
+     */
+    pub fn is_unbounded(&self) -> bool {
+        self.time_limit_ms.is_none() && self.max_evaluations.is_none()
+            && self.max_iterations.is_none() && self.max_no_improvement.is_none()
+    }
+
+    /**
+     * The conditions replaying a run that evaluated the given number of
+     * solutions: the time limit is replaced by that evaluation budget, so the
+     * same seed stops where the timed run stopped instead of wherever the
+     * clock does.
+     *
+     * @param evaluated: The number of solutions the run evaluated
+     * @return: The Termination of the replay
+     */
+    pub fn replay(&self, evaluated: u32) -> Termination {
+        Termination {
+            time_limit_ms: None,
+            max_evaluations: Some(evaluated),
+            ..self.clone()
+        }
+    }
+
+    /**
+     * Start tracking a run.
+     *
+     * @return: The progress of the new run
+     */
+    pub fn start(&self) -> Progress {
+        Progress {
+            termination: self.clone(),
+            time_start: Instant::now(),
+            evaluated: 0,
+            iterations: 0,
+            no_improvement: 0,
+            best_distance: f32::INFINITY,
+            checks: 0,
+            timed_out: false,
+        }
+    }
+}
+
+/**
+ * Progress struct
+ *
+ * The state of a run checked against its Termination.
+ *
+ * @field termination: The stopping conditions
+ * @field time_start: When the run started
+ * @field evaluated: The number of evaluated solutions
+ * @field iterations: The number of iterations
+ * @field no_improvement: The number of iterations since the best solution improved
+ * @field best_distance: The distance of the best solution so far
+ * @field checks: The number of calls to is_done, the clock is read only every few calls
+ * @field timed_out: Whether the clock passed the time limit, the run stays done once it did
+ */
+#[derive(Debug, Clone)]
+pub struct Progress {
+    termination: Termination,
+    time_start: Instant,
+    pub evaluated: u32,
+    pub iterations: u32,
+    no_improvement: u32,
+    best_distance: f32,
+    checks: u32,
+    timed_out: bool,
+}
+
+impl Progress {
+    /**
+     * Record an evaluated solution.
+     */
+    pub fn evaluate(&mut self) {
+        self.evaluated += 1;
+    }
+
+    /**
+     * Record an iteration and the distance of the best solution after it.
+     *
+     * @param best_distance: The distance of the best solution so far
+     */
+    pub fn iterate(&mut self, best_distance: f32) {
+        self.iterations += 1;
+        if best_distance < self.best_distance {
+            self.best_distance = best_distance;
+            self.no_improvement = 0;
+        } else {
+            self.no_improvement += 1;
+        }
+    }
+
+    /**
+     * Record the distance of the best solution without counting an iteration.
+     *
+     * @param best_distance: The distance of the best solution so far
+     */
+    pub fn improve(&mut self, best_distance: f32) {
+        if best_distance < self.best_distance {
+            self.best_distance = best_distance;
+            self.no_improvement = 0;
+        }
+    }
+
+    /**
+     * Elapsed time since the run started.
+     *
+     * @return: The elapsed time in milliseconds
+     */
+    pub fn elapsed_ms(&self) -> f64 {
+        self.time_start.elapsed().as_secs_f64() * 1000.0
+    }
+
+    /**
+     * Whether any of the stopping conditions holds.
+     *
+     * @return: True if the run should stop
+     */
+    pub fn is_done(&mut self) -> bool {
+        let termination = &self.termination;
+        if termination.max_evaluations.is_some_and(|max| self.evaluated >= max)
+            || termination.max_iterations.is_some_and(|max| self.iterations >= max)
+            || termination.max_no_improvement.is_some_and(|max| self.no_improvement >= max)
+            || termination.target_distance.is_some_and(|target| self.best_distance <= target)
+            || self.timed_out
+        {
+            return true;
+        }
+        // Reading the clock costs more than evaluating a move
+        self.checks = self.checks.wrapping_add(1);
+        if let Some(time_limit_ms) = termination.time_limit_ms {
+            if self.checks.is_multiple_of(64) || time_limit_ms <= 0.0 {
+                self.timed_out = self.elapsed_ms() >= time_limit_ms;
+                return self.timed_out;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_limit_stays_done() {
+        let mut progress = Termination::new().with_time_limit(0.5).start();
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        // The clock is read on the 64th check
+        assert!((0..64).any(|_| progress.is_done()));
+        assert!((0..256).all(|_| progress.is_done()));
+    }
+
+    #[test]
+    fn replay_swaps_the_time_limit_for_the_evaluations() {
+        let termination = Termination::new().with_time_limit(1000.0).with_max_no_improvement(10);
+        let replay = termination.replay(42);
+        assert_eq!(replay, Termination::new().with_max_evaluations(42).with_max_no_improvement(10));
+
+        let mut progress = replay.start();
+        progress.evaluated = 41;
+        assert!(!progress.is_done());
+        progress.evaluate();
+        assert!(progress.is_done());
+    }
+}
@@ -0,0 +1,646 @@
+use crate::distance::DistanceMatrix;
+use crate::utils;
+
+/**
+ * The number of moves between two recomputations of a TourLength.
+ */
+const VERIFY_INTERVAL: u32 = 1000;
+
+/**
+ * The largest relative difference between a tracked and a recomputed length
+ * put down to rounding.
+ */
+const TOLERANCE: f64 = 1e-5;
+
+/**
+ * Tour trait
+ *
+ * An oriented Hamiltonian cycle that supports the operations of 2-opt style
+ * moves without going through tour positions. Only Lin-Kernighan runs on it:
+ * greedy, steepest, simulated annealing, tabu search, random walk and
+ * iterated local search enumerate their moves by tour position, so they
+ * keep the tour in a plain array and Move::apply shifts it in place, O(n)
+ * per move at worst. Moving them onto this trait would mean rewriting their
+ * neighbourhoods around next/prev and is not done.
+ */
+pub trait Tour {
+    /**
+     * The number of cities.
+     */
+    fn len(&self) -> usize;
+
+    /**
+     * Whether the tour has no cities.
+     */
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /**
+     * The city after a city.
+     */
+    fn next(&self, city: u32) -> u32;
+
+    /**
+     * The city before a city.
+     */
+    fn prev(&self, city: u32) -> u32;
+
+    /**
+     * Whether b lies on the path from a to c, both included.
+     */
+    fn between(&self, a: u32, b: u32, c: u32) -> bool;
+
+    /**
+     * Reverse the path from a to b, so that prev(a) is followed by b and
+     * a by the former next(b).
+     *
+     * @param a: The first city of the path
+     * @param b: The last city of the path
+     */
+    fn reverse(&mut self, a: u32, b: u32);
+
+    /**
+     * The cities in tour order, starting with city 0.
+     */
+    fn order(&self) -> Vec<u32> {
+        let mut order = Vec::with_capacity(self.len());
+        if self.is_empty() {
+            return order;
+        }
+        let mut city = 0;
+        for _ in 0..self.len() {
+            order.push(city);
+            city = self.next(city);
+        }
+        order
+    }
+}
+
+/**
+ * Whether the key b lies between the keys a and c going forward on a cycle.
+ */
+fn cyclic_between<K: PartialOrd>(a: K, b: K, c: K) -> bool {
+    if a <= c { a <= b && b <= c } else { b >= a || b <= c }
+}
+
+/**
+ * ArrayTour struct
+ *
+ * A tour stored as an array with the position of every city. Reversals cost
+ * O(n) at worst: the shorter of the path and the rest of the tour is
+ * reversed, flipping the orientation of the array in the second case.
+ *
+ * @field order: The cities in array order
+ * @field position: The index of every city in the array
+ * @field reversed: Whether the tour runs backwards through the array
+ */
+#[derive(Debug, Clone)]
+pub struct ArrayTour {
+    order: Vec<u32>,
+    position: Vec<usize>,
+    reversed: bool,
+}
+
+impl ArrayTour {
+    /**
+     * Create a tour visiting the cities in the given order.
+     *
+     * @param order: A permutation of the cities
+     * @return: The tour
+     */
+    pub fn new(order: Vec<u32>) -> ArrayTour {
+        let mut position = vec![0; order.len()];
+        for (i, &city) in order.iter().enumerate() {
+            position[city as usize] = i;
+        }
+        ArrayTour { order, position, reversed: false }
+    }
+
+    /**
+     * Reverse the cities between two array indices, wrapping around the end.
+     */
+    fn reverse_indices(&mut self, from: usize, to: usize) {
+        let n = self.order.len();
+        let length = (to + n - from) % n + 1;
+        let (mut i, mut j) = (from, to);
+        for _ in 0..length / 2 {
+            self.order.swap(i, j);
+            self.position[self.order[i] as usize] = i;
+            self.position[self.order[j] as usize] = j;
+            i = (i + 1) % n;
+            j = (j + n - 1) % n;
+        }
+    }
+}
+
+impl Tour for ArrayTour {
+    fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    #[inline]
+    fn next(&self, city: u32) -> u32 {
+        let n = self.order.len();
+        let offset = if self.reversed { n - 1 } else { 1 };
+        self.order[(self.position[city as usize] + offset) % n]
+    }
+
+    #[inline]
+    fn prev(&self, city: u32) -> u32 {
+        let n = self.order.len();
+        let offset = if self.reversed { 1 } else { n - 1 };
+        self.order[(self.position[city as usize] + offset) % n]
+    }
+
+    fn between(&self, a: u32, b: u32, c: u32) -> bool {
+        let (a, c) = if self.reversed { (c, a) } else { (a, c) };
+        cyclic_between(self.position[a as usize], self.position[b as usize], self.position[c as usize])
+    }
+
+    fn reverse(&mut self, a: u32, b: u32) {
+        let n = self.order.len();
+        // The path in array order
+        let (a, b) = if self.reversed { (b, a) } else { (a, b) };
+        let (from, to) = (self.position[a as usize], self.position[b as usize]);
+        let length = (to + n - from) % n + 1;
+        if 2 * length <= n {
+            self.reverse_indices(from, to);
+        } else {
+            // Reversing the rest of the tour and the orientation reverses the path
+            if length < n {
+                self.reverse_indices((to + 1) % n, (from + n - 1) % n);
+            }
+            self.reversed = !self.reversed;
+        }
+    }
+}
+
+/**
+ * A segment of a TwoLevelList.
+ *
+ * @field cities: The cities of the segment
+ * @field reversed: Whether the tour runs backwards through the cities
+ * @field rank: The index of the segment in the list of segments
+ */
+#[derive(Debug, Clone)]
+struct Segment {
+    cities: Vec<u32>,
+    reversed: bool,
+    rank: usize,
+}
+
+impl Segment {
+    fn first(&self) -> u32 {
+        if self.reversed { *self.cities.last().unwrap() } else { self.cities[0] }
+    }
+
+    fn last(&self) -> u32 {
+        if self.reversed { self.cities[0] } else { *self.cities.last().unwrap() }
+    }
+}
+
+/**
+ * TwoLevelList struct
+ *
+ * A tour split into about sqrt(n) segments, each with its own reversal bit,
+ * themselves kept in a list. A reversal splits at most two segments at the
+ * ends of the path, then reverses the order of the segments in between and
+ * flips their bits, which costs O(sqrt(n)) instead of O(n). The segments are
+ * rebuilt once splitting has doubled their number.
+ *
+ * @field segments: The segments, in no particular order
+ * @field order: The indices of the segments in tour order
+ * @field segment_of: The segment of every city
+ * @field index_of: The index of every city in its segment
+ * @field reversed: Whether the tour runs backwards through the list
+ * @field group_size: The size of the segments after a rebuild
+ */
+#[derive(Debug, Clone)]
+pub struct TwoLevelList {
+    segments: Vec<Segment>,
+    order: Vec<usize>,
+    segment_of: Vec<usize>,
+    index_of: Vec<usize>,
+    reversed: bool,
+    group_size: usize,
+}
+
+impl TwoLevelList {
+    /**
+     * Create a tour visiting the cities in the given order.
+     *
+     * @param order: A permutation of the cities
+     * @return: The tour
+     */
+    pub fn new(order: &[u32]) -> TwoLevelList {
+        let group_size = ((order.len() as f64).sqrt().ceil() as usize).max(1);
+        let mut list = TwoLevelList {
+            segments: Vec::new(),
+            order: Vec::new(),
+            segment_of: vec![0; order.len()],
+            index_of: vec![0; order.len()],
+            reversed: false,
+            group_size,
+        };
+        list.rebuild(order);
+        list
+    }
+
+    /**
+     * Split the cities into segments of group_size in the given order.
+     */
+    fn rebuild(&mut self, order: &[u32]) {
+        self.segments.clear();
+        for (rank, chunk) in order.chunks(self.group_size).enumerate() {
+            for (i, &city) in chunk.iter().enumerate() {
+                self.segment_of[city as usize] = rank;
+                self.index_of[city as usize] = i;
+            }
+            self.segments.push(Segment { cities: chunk.to_vec(), reversed: false, rank });
+        }
+        self.order = (0..self.segments.len()).collect();
+    }
+
+    /**
+     * The cities in list order, ignoring the global orientation.
+     */
+    fn forward_order(&self) -> Vec<u32> {
+        self.order
+            .iter()
+            .flat_map(|&s| {
+                let segment = &self.segments[s];
+                let cities: Box<dyn Iterator<Item = &u32>> =
+                    if segment.reversed { Box::new(segment.cities.iter().rev()) } else { Box::new(segment.cities.iter()) };
+                cities.copied()
+            })
+            .collect()
+    }
+
+    /**
+     * The city after a city in list order.
+     */
+    fn forward_next(&self, city: u32) -> u32 {
+        let segment = &self.segments[self.segment_of[city as usize]];
+        let i = self.index_of[city as usize];
+        if !segment.reversed && i + 1 < segment.cities.len() {
+            return segment.cities[i + 1];
+        }
+        if segment.reversed && i > 0 {
+            return segment.cities[i - 1];
+        }
+        let next = self.order[(segment.rank + 1) % self.order.len()];
+        self.segments[next].first()
+    }
+
+    /**
+     * The city before a city in list order.
+     */
+    fn forward_prev(&self, city: u32) -> u32 {
+        let segment = &self.segments[self.segment_of[city as usize]];
+        let i = self.index_of[city as usize];
+        if segment.reversed && i + 1 < segment.cities.len() {
+            return segment.cities[i + 1];
+        }
+        if !segment.reversed && i > 0 {
+            return segment.cities[i - 1];
+        }
+        let m = self.order.len();
+        let prev = self.order[(segment.rank + m - 1) % m];
+        self.segments[prev].last()
+    }
+
+    /**
+     * A key ordering the cities along the list.
+     */
+    fn key(&self, city: u32) -> (usize, usize) {
+        let segment = &self.segments[self.segment_of[city as usize]];
+        let i = self.index_of[city as usize];
+        let offset = if segment.reversed { segment.cities.len() - 1 - i } else { i };
+        (segment.rank, offset)
+    }
+
+    /**
+     * Store the cities of a segment in list order and clear its bit.
+     */
+    fn normalise(&mut self, s: usize) {
+        let segment = &mut self.segments[s];
+        if segment.reversed {
+            segment.cities.reverse();
+            segment.reversed = false;
+            for (i, &city) in segment.cities.iter().enumerate() {
+                self.index_of[city as usize] = i;
+            }
+        }
+    }
+
+    /**
+     * Make a city the first one of its segment in list order.
+     */
+    fn split_before(&mut self, city: u32) {
+        let s = self.segment_of[city as usize];
+        if self.segments[s].first() == city { return; }
+        self.normalise(s);
+        let i = self.index_of[city as usize];
+        let tail = self.segments[s].cities.split_off(i);
+        let t = self.segments.len();
+        for (j, &moved) in tail.iter().enumerate() {
+            self.segment_of[moved as usize] = t;
+            self.index_of[moved as usize] = j;
+        }
+        let rank = self.segments[s].rank + 1;
+        self.segments.push(Segment { cities: tail, reversed: false, rank });
+        self.order.insert(rank, t);
+        for r in rank + 1..self.order.len() {
+            self.segments[self.order[r]].rank = r;
+        }
+    }
+
+    /**
+     * Reverse the path from a to b in list order.
+     */
+    fn forward_reverse(&mut self, a: u32, b: u32) {
+        if a == b { return; }
+        let (sa, sb) = (self.segment_of[a as usize], self.segment_of[b as usize]);
+        // A path inside a single segment is reversed in place
+        if sa == sb && self.key(a) <= self.key(b) {
+            self.normalise(sa);
+            let (i, j) = (self.index_of[a as usize], self.index_of[b as usize]);
+            let segment = &mut self.segments[sa];
+            segment.cities[i..=j].reverse();
+            for (k, &city) in segment.cities.iter().enumerate().take(j + 1).skip(i) {
+                self.index_of[city as usize] = k;
+            }
+            return;
+        }
+
+        self.split_before(a);
+        let after_b = self.forward_next(b);
+        self.split_before(after_b);
+
+        let m = self.order.len();
+        let (first, last) = (self.segments[self.segment_of[a as usize]].rank, self.segments[self.segment_of[b as usize]].rank);
+        let count = (last + m - first) % m + 1;
+        // Reversing the other segments and the orientation reverses the path
+        let (first, last, count) = if 2 * count > m {
+            self.reversed = !self.reversed;
+            ((last + 1) % m, (first + m - 1) % m, m - count)
+        } else {
+            (first, last, count)
+        };
+        for k in 0..count {
+            let s = self.order[(first + k) % m];
+            self.segments[s].reversed = !self.segments[s].reversed;
+        }
+        for k in 0..count / 2 {
+            self.order.swap((first + k) % m, (last + m - k) % m);
+        }
+        for k in 0..count {
+            let r = (first + k) % m;
+            self.segments[self.order[r]].rank = r;
+        }
+
+        // Splitting adds up to two segments per reversal
+        if self.order.len() > 2 * self.len().div_ceil(self.group_size) {
+            let order = self.forward_order();
+            self.rebuild(&order);
+        }
+    }
+}
+
+impl Tour for TwoLevelList {
+    fn len(&self) -> usize {
+        self.segment_of.len()
+    }
+
+    fn next(&self, city: u32) -> u32 {
+        if self.reversed { self.forward_prev(city) } else { self.forward_next(city) }
+    }
+
+    fn prev(&self, city: u32) -> u32 {
+        if self.reversed { self.forward_next(city) } else { self.forward_prev(city) }
+    }
+
+    fn between(&self, a: u32, b: u32, c: u32) -> bool {
+        let (a, c) = if self.reversed { (c, a) } else { (a, c) };
+        cyclic_between(self.key(a), self.key(b), self.key(c))
+    }
+
+    fn reverse(&mut self, a: u32, b: u32) {
+        if self.reversed {
+            self.forward_reverse(b, a);
+        } else {
+            self.forward_reverse(a, b);
+        }
+    }
+}
+
+/**
+ * TourLength struct
+ *
+ * The length of a tour kept up to date from the deltas of the applied moves.
+ * The deltas are summed in f64, and the length is recomputed from scratch
+ * every VERIFY_INTERVAL moves, so it cannot drift. Debug builds panic when
+ * the tracked and the recomputed length disagree, which means a delta is wrong.
+ *
+ * @field length: The current length
+ * @field pending: The number of moves since the last recomputation
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct TourLength {
+    length: f64,
+    pending: u32,
+}
+
+impl TourLength {
+    /**
+     * Compute the length of a tour.
+     *
+     * @param tour: The tour
+     * @param distance_matrix: The distance matrix of the TSP problem
+     * @return: The tracked length
+     */
+    pub fn new(tour: &[u32], distance_matrix: &DistanceMatrix) -> TourLength {
+        TourLength { length: utils::tour_length(tour, distance_matrix), pending: 0 }
+    }
+
+    /**
+     * The current length.
+     */
+    #[inline]
+    pub fn get(&self) -> f32 {
+        self.length as f32
+    }
+
+    /**
+     * Account for an applied move.
+     *
+     * @param delta: The delta fitness of the move
+     */
+    #[inline]
+    pub fn add(&mut self, delta: f32) {
+        self.length += delta as f64;
+        self.pending += 1;
+    }
+
+    /**
+     * Whether the length is due for a recomputation.
+     */
+    #[inline]
+    pub fn is_due(&self) -> bool {
+        self.pending >= VERIFY_INTERVAL
+    }
+
+    /**
+     * Recompute the length if it is due.
+     *
+     * @param tour: The tour after the moves
+     * @param distance_matrix: The distance matrix of the TSP problem
+     */
+    #[inline]
+    pub fn check(&mut self, tour: &[u32], distance_matrix: &DistanceMatrix) {
+        if self.is_due() {
+            self.verify(tour, distance_matrix);
+        }
+    }
+
+    /**
+     * Recompute the length from scratch and replace the tracked one.
+     *
+     * @param tour: The tour after the moves
+     * @param distance_matrix: The distance matrix of the TSP problem
+     */
+    pub fn verify(&mut self, tour: &[u32], distance_matrix: &DistanceMatrix) {
+        let length = utils::tour_length(tour, distance_matrix);
+        debug_assert!(
+            (self.length - length).abs() <= TOLERANCE * length.abs().max(1.0),
+            "tracked tour length {} differs from the recomputed {} after {} moves",
+            self.length, length, self.pending,
+        );
+        self.length = length;
+        self.pending = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::neighbourhood::Move;
+
+    /**
+     * The reference model: the cities in tour order in a plain array.
+     */
+    struct Naive(Vec<u32>);
+
+    impl Naive {
+        fn position(&self, city: u32) -> usize {
+            self.0.iter().position(|&other| other == city).unwrap()
+        }
+
+        fn next(&self, city: u32) -> u32 {
+            self.0[(self.position(city) + 1) % self.0.len()]
+        }
+
+        fn prev(&self, city: u32) -> u32 {
+            let n = self.0.len();
+            self.0[(self.position(city) + n - 1) % n]
+        }
+
+        fn between(&self, a: u32, b: u32, c: u32) -> bool {
+            let mut city = a;
+            loop {
+                if city == b { return true; }
+                if city == c { return false; }
+                city = self.next(city);
+            }
+        }
+
+        fn reverse(&mut self, a: u32, b: u32) {
+            let n = self.0.len();
+            let (mut i, mut j) = (self.position(a), self.position(b));
+            let length = (j + n - i) % n + 1;
+            for _ in 0..length / 2 {
+                self.0.swap(i, j);
+                i = (i + 1) % n;
+                j = (j + n - 1) % n;
+            }
+        }
+    }
+
+    /**
+     * Apply random reversals to a tour and to the model, comparing them after every one.
+     */
+    fn check_against_naive<T: Tour>(new: impl Fn(&[u32]) -> T) {
+        let mut rng = StdRng::seed_from_u64(0);
+        for n in [1, 2, 3, 5, 16, 50] {
+            let order = utils::random_permutation(n, &mut rng);
+            let mut tour = new(&order);
+            let mut naive = Naive(order);
+            for _ in 0..300 {
+                let (a, b) = (rng.gen_range(0..n as u32), rng.gen_range(0..n as u32));
+                tour.reverse(a, b);
+                naive.reverse(a, b);
+
+                assert_eq!(tour.len(), n);
+                for city in 0..n as u32 {
+                    assert_eq!(tour.next(city), naive.next(city), "next({}) after reversing {}..{}", city, a, b);
+                    assert_eq!(tour.prev(city), naive.prev(city), "prev({}) after reversing {}..{}", city, a, b);
+                }
+                for _ in 0..10 {
+                    let (a, b, c) = (rng.gen_range(0..n as u32), rng.gen_range(0..n as u32), rng.gen_range(0..n as u32));
+                    assert_eq!(tour.between(a, b, c), naive.between(a, b, c), "between({}, {}, {})", a, b, c);
+                }
+                let start = naive.position(0);
+                let expected: Vec<u32> = naive.0[start..].iter().chain(&naive.0[..start]).copied().collect();
+                assert_eq!(tour.order(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn array_tour_matches_naive() {
+        check_against_naive(|order| ArrayTour::new(order.to_vec()));
+    }
+
+    #[test]
+    fn two_level_list_matches_naive() {
+        check_against_naive(TwoLevelList::new);
+    }
+
+    #[test]
+    fn tour_length_follows_random_moves() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for symmetric in [true, false] {
+            let distance_matrix = utils::random_distance_matrix(30, symmetric, &mut rng);
+            let mut tour = utils::random_permutation(30, &mut rng);
+            let mut length = TourLength::new(&tour, &distance_matrix);
+            // Enough moves for check to recompute the length along the way
+            for _ in 0..2 * VERIFY_INTERVAL + 300 {
+                let (i, j) = utils::random_pair(30, &mut rng);
+                let (i, j) = (i.min(j), i.max(j));
+                if j < i + 2 || (j + 1) % 30 == i { continue; }
+                let possible_move = Move::TwoOpt { i, j };
+                length.add(possible_move.delta(&distance_matrix, &tour, symmetric));
+                possible_move.apply(&mut tour);
+                length.check(&tour, &distance_matrix);
+                assert_eq!(length.get() as f64, utils::tour_length(&tour, &distance_matrix));
+            }
+            length.verify(&tour, &distance_matrix);
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "differs from the recomputed")]
+    fn tour_length_catches_a_wrong_delta() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let distance_matrix = utils::random_distance_matrix(10, true, &mut rng);
+        let tour = utils::random_permutation(10, &mut rng);
+        let mut length = TourLength::new(&tour, &distance_matrix);
+        length.add(1.0);
+        length.verify(&tour, &distance_matrix);
+    }
+}
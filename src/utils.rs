@@ -1,5 +1,4 @@
-use std::fs::File;
-use std::io::{self, BufRead};
+use std::io;
 
 use rand::prelude::*;
 
@@ -7,16 +6,25 @@ use std::io::Write;
 use serde::{Serialize, Deserialize};
 use serde_json;
 
+use crate::distance::DistanceMatrix;
+use crate::instance::{Instance, InstanceError};
+use crate::lower_bound::HeldKarp;
+use crate::neighbourhood::Reconnection;
+use crate::solver::SolveResult;
+
 
 // Struct to save the solution
 #[derive(Serialize, Deserialize)]
 struct Solution {
     best_distance: f32,
+    lower_bound: Option<f64>,
     best_solution: Vec<i64>,
     distances: Vec<f32>,
     runtimes: Vec<u128>,
     steps: Vec<u32>,
     evaluated: Vec<u32>,
+    metadata: serde_json::Value,
+    seeds: Vec<u64>,
 }
 
 /**
@@ -24,22 +32,25 @@ struct Solution {
  *
  * @param instance_name: Name of the instance
  * @param algorithm: Name of the algorithm
- * @param solutions: Vector of solutions
- * @param distances: Vector of distances
+ * @param metadata: Parameters of the algorithm
+ * @param results: Vector of results of the runs
  * @param elapsed_time: Vector of elapsed times
- * @param steps: Vector of steps
- * @param evaluated: Vector of evaluated solutions
+ * @param lower_bound: A lower bound on the optimal distance of the instance, if known
  */
-pub fn save_solution(instance_name: &str, algorithm: &str, solutions: &Vec<Vec<u32>>, distances: &Vec<f32>, elapsed_time: &Vec<u128>, steps: &Vec<u32>, evaluated: &Vec<u32>) {
+pub fn save_solution(instance_name: &str, algorithm: &str, metadata: &serde_json::Value, results: &[SolveResult], elapsed_time: &[u128], lower_bound: Option<f64>) {
     // Schema
     // {
     //    "best_distance": 123.45,
+    //    "lower_bound": 120.5,
     //    "best_solution": [1, 2, 3, 4, 5],
     //    "distances": [123.45, 123.45, 123.45],
     //    "runtimes": [123, 123, 123]
     //    "steps": [123, 123, 123]
     //    "evaluated": [123, 123, 123]
+    //    "metadata": {"parameter": 123}
+    //    "seeds": [1, 2, 3]
     // }
+    let distances: Vec<f32> = results.iter().map(|result| result.distance).collect();
     let index_of_min_dist = distances.iter().enumerate().min_by(|(_, a), (_, b)| a.total_cmp(b)).map(|(index, _)| index);
     std::fs::create_dir_all(format!("results/{}", instance_name)).unwrap();
     let file_path = format!("results/{}/{}.json", instance_name, algorithm);
@@ -47,97 +58,65 @@ pub fn save_solution(instance_name: &str, algorithm: &str, solutions: &Vec<Vec<u
 
     // Write the best distance
     let best_distance = distances[index_of_min_dist.unwrap()];
-    let best_solution = &solutions[index_of_min_dist.unwrap()];
-    let best_solution = best_solution.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(", ");
-    let best_solution = format!("[{}]", best_solution);
-    let best_distance = format!("{:}", best_distance);
-    let best_distance = best_distance.parse::<f32>().unwrap();
-    let best_solution = serde_json::from_str(&best_solution).unwrap();
+    let best_solution = &results[index_of_min_dist.unwrap()].tour;
+    let best_solution = best_solution.iter().map(|&x| x as i64).collect();
     let data = Solution {
         best_distance,
+        lower_bound,
         best_solution,
-        distances: distances.clone(),
-        runtimes: elapsed_time.clone(),
-        steps: steps.clone(),
-        evaluated: evaluated.clone(),
+        distances,
+        runtimes: elapsed_time.to_vec(),
+        steps: results.iter().map(|result| result.steps).collect(),
+        evaluated: results.iter().map(|result| result.evaluated).collect(),
+        metadata: metadata.clone(),
+        seeds: results.iter().map(|result| result.seed).collect(),
     };
     let json = serde_json::to_string_pretty(&data).unwrap();
     file.write_all(json.as_bytes()).unwrap();
 }
 
 
-/**
- * A struct to represent a coordinate in a 2D plane.
- *
- * @field x: The x-coordinate of the point.
- * @field y: The y-coordinate of the point.
- */
-struct Coordinate {
-    x: f32,
-    y: f32,
-}
-
-/**
- * Calculate the Euclidean distance between two coordinates.
- *
- * @param coord1: The first coordinate.
- * @param coord2: The second coordinate.
- * @return The Euclidean distance between the two coordinates.
- */
-fn euclidean_distance(coord1: &Coordinate, coord2: &Coordinate) -> f32 {
-    ((coord2.x as f32 - coord1.x as f32).powi(2) + (coord2.y as f32 - coord1.y as f32).powi(2)).sqrt()
+// Struct to cache the lower bound of an instance
+#[derive(Serialize, Deserialize)]
+struct LowerBound {
+    bound: f64,
+    iterations: u32,
+    max_iterations: u32,
 }
 
 /**
- * Calculate the distance matrix between a set of coordinates.
+ * The Held-Karp lower bound of an instance, with the steps of
+ * HeldKarp::for_size. It is computed once and saved as
+ * results/lower_bounds/{instance_name}.json, which every runner reads.
  *
- * @param coordinates: The set of coordinates.
- * @return The distance matrix between the coordinates.
+ * @param instance: The TSP problem
+ * @return: The lower bound
  */
-fn calculate_distance_matrix(coordinates: &[Coordinate]) -> Vec<Vec<f32>> {
-    let mut distance_matrix = vec![vec![0.0; coordinates.len()]; coordinates.len()];
-    for i in 0..coordinates.len() {
-        for j in 0..coordinates.len() {
-            let dist = euclidean_distance(&coordinates[i], &coordinates[j]);
-            distance_matrix[i][j] = dist;
-            distance_matrix[j][i] = dist;
-        }
+pub fn lower_bound(instance: &Instance) -> f64 {
+    let held_karp = HeldKarp::for_size(instance.dimension);
+    let file_path = format!("results/lower_bounds/{}.json", instance.name);
+    let cached = std::fs::read_to_string(&file_path).ok().and_then(|json| serde_json::from_str::<LowerBound>(&json).ok());
+    if let Some(cached) = cached.filter(|cached| cached.max_iterations == held_karp.max_iterations) {
+        return cached.bound;
     }
-    distance_matrix
+
+    let bound = held_karp.bound(&instance.distance_matrix);
+    let data = LowerBound { bound: bound.bound, iterations: bound.iterations, max_iterations: held_karp.max_iterations };
+    std::fs::create_dir_all("results/lower_bounds").unwrap();
+    let json = serde_json::to_string_pretty(&data).unwrap();
+    std::fs::File::create(file_path).unwrap().write_all(json.as_bytes()).unwrap();
+    bound.bound
 }
 
+
 /**
 * Read an instance from a file.
 *
 * @param file_path: The path to the file.
-* @return The distance matrix between the coordinates.
+* @return The parsed instance with its distance matrix.
 */
-pub fn read_instance(file_path: &str) -> io::Result<Vec<Vec<f32>>> {
-    let file = File::open(file_path)?;
-    let reader = io::BufReader::new(file);
-
-    let mut coordinates = Vec::new();
-    let mut reading_coordinates = false;
-    for line in reader.lines() {
-        let line = line?;
-        if line == "NODE_COORD_SECTION" {
-            reading_coordinates = true;
-            continue;
-        } else if line == "EOF" {
-            break;
-        }
-
-        if reading_coordinates {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 3 {
-                if let (Ok(x), Ok(y)) = (parts[1].parse::<f32>(), parts[2].parse::<f32>()) {
-                    coordinates.push(Coordinate { x, y });
-                }
-            }
-        }
-    }
-    let distance_matrix = calculate_distance_matrix(&coordinates);
-    Ok(distance_matrix)
+pub fn read_instance(file_path: &str) -> Result<Instance, InstanceError> {
+    Instance::from_file(file_path)
 }
 
 /**
@@ -147,12 +126,24 @@ pub fn read_instance(file_path: &str) -> io::Result<Vec<Vec<f32>>> {
  * @param distance_matrix: The distance matrix between the coordinates.
  * @return The total distance of the tour.
  */
-pub fn calculate_tour_distance(tour: &[u32], distance_matrix: &[Vec<f32>]) -> io::Result<f32> {
+pub fn calculate_tour_distance(tour: &[u32], distance_matrix: &DistanceMatrix) -> io::Result<f32> {
+    Ok(tour_length(tour, distance_matrix) as f32)
+}
+
+/**
+ * Calculates the total distance of a tour, summed in f64 so that long tours
+ * do not lose precision.
+ *
+ * @param tour: The tour.
+ * @param distance_matrix: The distance matrix between the coordinates.
+ * @return The total distance of the tour.
+ */
+pub fn tour_length(tour: &[u32], distance_matrix: &DistanceMatrix) -> f64 {
     let mut distance = 0.0;
     for i in 0..tour.len() {
-        distance += distance_matrix[tour[i] as usize][tour[(i + 1) % tour.len()] as usize];
+        distance += distance_matrix.get(tour[i] as usize, tour[(i + 1) % tour.len()] as usize) as f64;
     }
-    Ok(distance)
+    distance
 }
 
 
@@ -165,66 +156,183 @@ pub fn calculate_tour_distance(tour: &[u32], distance_matrix: &[Vec<f32>]) -> io
     * @param next_j: The second node of the second edge
     * @return: The delta fitness
     */
-pub fn get_delta_intra_route(distance_matrix: &[Vec<f32>], i: u32, next_i: u32, j: u32, next_j: u32) -> f32 {
-    distance_matrix[i as usize][j as usize] + distance_matrix[next_i as usize][next_j as usize]
-    - (distance_matrix[i as usize][next_i as usize] + distance_matrix[j as usize][next_j as usize])
+pub fn get_delta_intra_route(distance_matrix: &DistanceMatrix, i: u32, next_i: u32, j: u32, next_j: u32) -> f32 {
+    distance_matrix.get(i as usize, j as usize) + distance_matrix.get(next_i as usize, next_j as usize)
+    - (distance_matrix.get(i as usize, next_i as usize) + distance_matrix.get(j as usize, next_j as usize))
 }
 
 /**
-* Swap 2 edges
-*
-* @param current_tour: The current tour
-* @param next_i: The second node index of the first edge
-* @param j: The first node index of the second edge
-* @param best_tour: The best tour found
-* @return: The modified best tour found
-*/
-pub fn swap_2_edges(current_tour: &[u32], next_i: usize, j: usize, mut best_tour: Vec<u32>) -> Vec<u32> {
-    // Extract the slice [:next_i)
-    let first_part = current_tour[..next_i].to_vec();
-    // Extract and reverse the slice [next_i, (j + 1))
-    let mut middle_part_rev = current_tour[next_i..=j].to_vec();
-    middle_part_rev.reverse();
-    // Extract the slice [(j + 1):]
-    let last_part = current_tour[(j + 1)..].to_vec();
-    // Combine the slices
-    best_tour.clear();
-    best_tour.extend_from_slice(&first_part);
-    best_tour.extend_from_slice(&middle_part_rev);
-    best_tour.extend_from_slice(&last_part);
+    * Calculate the delta of a 2-opt move given by tour positions
+    *
+    * On asymmetric instances the reversed segment is traversed in the
+    * opposite direction, so its inner edges are re-priced as well.
+    *
+    * @param tour: The current tour
+    * @param i: The position of the first node of the first edge
+    * @param j: The position of the first node of the second edge, i < j
+    * @param symmetric: Whether the distance matrix is symmetric
+    * @return: The delta fitness
+    */
+pub fn get_delta_2opt(distance_matrix: &DistanceMatrix, tour: &[u32], i: usize, j: usize, symmetric: bool) -> f32 {
+    let n = tour.len();
+    let next_i = (i + 1) % n;
+    let next_j = (j + 1) % n;
+    let mut delta = get_delta_intra_route(distance_matrix, tour[i], tour[next_i], tour[j], tour[next_j]);
+    if !symmetric {
+        delta += get_delta_reversal(distance_matrix, tour, next_i, j);
+    }
+    delta
+}
 
-    best_tour
+/**
+    * Calculate the change of length of a segment traversed in the opposite
+    * direction, always 0 on symmetric instances
+    *
+    * @param tour: The current tour
+    * @param from: The position of the first node of the segment
+    * @param to: The position of the last node of the segment, from <= to
+    * @return: The delta fitness
+    */
+pub fn get_delta_reversal(distance_matrix: &DistanceMatrix, tour: &[u32], from: usize, to: usize) -> f32 {
+    let mut delta = 0.0;
+    for k in from..to {
+        let (a, b) = (tour[k] as usize, tour[k + 1] as usize);
+        delta += distance_matrix.get(b, a) - distance_matrix.get(a, b);
+    }
+    delta
 }
 
+/**
+    * Calculate the delta of a 3-opt move given by tour positions
+    *
+    * The edges (i, i + 1), (j, j + 1) and (k, k + 1) split the tour into
+    * A, B = [i + 1, j] and C = [j + 1, k], which are reconnected as given.
+    *
+    * @param tour: The current tour
+    * @param i: The position of the first node of the first edge
+    * @param j: The position of the first node of the second edge, i < j
+    * @param k: The position of the first node of the third edge, j < k
+    * @param reconnection: How the segments are joined again
+    * @param symmetric: Whether the distance matrix is symmetric
+    * @return: The delta fitness
+    */
+pub fn get_delta_3opt(distance_matrix: &DistanceMatrix, tour: &[u32], i: usize, j: usize, k: usize, reconnection: Reconnection, symmetric: bool) -> f32 {
+    let n = tour.len();
+    let d = |x: u32, y: u32| distance_matrix.get(x as usize, y as usize);
+    let (a, b) = (tour[i], tour[i + 1]);
+    let (c, e) = (tour[j], tour[j + 1]);
+    let (f, g) = (tour[k], tour[(k + 1) % n]);
+
+    let removed = d(a, b) + d(c, e) + d(f, g);
+    let added = match reconnection {
+        Reconnection::ReverseFirst => d(a, c) + d(b, e) + d(f, g),
+        Reconnection::ReverseSecond => d(a, b) + d(c, f) + d(e, g),
+        Reconnection::ReverseBoth => d(a, c) + d(b, f) + d(e, g),
+        Reconnection::ReverseAll => d(a, f) + d(e, c) + d(b, g),
+        Reconnection::Exchange => d(a, e) + d(f, b) + d(c, g),
+        Reconnection::ExchangeReverseFirst => d(a, e) + d(f, c) + d(b, g),
+        Reconnection::ExchangeReverseSecond => d(a, f) + d(e, b) + d(c, g),
+    };
+    let mut delta = added - removed;
+    if !symmetric {
+        let (reverse_first, reverse_second) = reconnection.reversed();
+        if reverse_first {
+            delta += get_delta_reversal(distance_matrix, tour, i + 1, j);
+        }
+        if reverse_second {
+            delta += get_delta_reversal(distance_matrix, tour, j + 1, k);
+        }
+    }
+    delta
+}
+
+/**
+ * Position of every node in a tour.
+ *
+ * @param tour: The tour.
+ * @return The positions, indexed by node.
+ */
+pub fn positions(tour: &[u32]) -> Vec<usize> {
+    let mut position = vec![0; tour.len()];
+    for (i, &node) in tour.iter().enumerate() {
+        position[node as usize] = i;
+    }
+    position
+}
 
 /**
  * Generate a random permutation of range 0 to n-1.
  *
  * @param n: Permutation size.
+ * @param rng: The random number generator.
  * @return A random permuatation.
  */
-pub fn random_permutation(n: usize) -> Vec<u32> {
+pub fn random_permutation(n: usize, rng: &mut impl Rng) -> Vec<u32> {
     let mut permutation: Vec<u32> = (0..n as u32).collect();
-    let mut rng = rand::thread_rng();
 
     for i in (1..n).rev() {
         let j = rng.gen_range(0..=i);
-        permutation.swap(i as usize, j as usize);
+        permutation.swap(i, j);
     }
     permutation
 }
 
+/**
+ * Get the tour a solver starts from.
+ *
+ * @param initial_tour: The tour supplied by the caller, if any
+ * @param n: The number of nodes
+ * @param rng: The random number generator.
+ * @return The supplied tour if it is a permutation of 0 to n-1, otherwise a random one.
+ */
+pub fn initial_tour(initial_tour: Option<Vec<u32>>, n: usize, rng: &mut impl Rng) -> Result<Vec<u32>, &'static str> {
+    match initial_tour {
+        Some(tour) => {
+            let mut visited = vec![false; n];
+            for &node in &tour {
+                if node as usize >= n || visited[node as usize] {
+                    return Err("Initial tour is not a permutation of the nodes");
+                }
+                visited[node as usize] = true;
+            }
+            if tour.len() != n {
+                return Err("Initial tour is not a permutation of the nodes");
+            }
+            Ok(tour)
+        },
+        None => Ok(random_permutation(n, rng)),
+    }
+}
+
 /**
  * Generate a random pair of indices.
  *
  * @param n: The number of indices.
+ * @param rng: The random number generator.
  * @return A random pair of indices.
  */
-pub fn random_pair(n: usize) -> (usize, usize) {
-    let mut rng = rand::thread_rng();
+pub fn random_pair(n: usize, rng: &mut impl Rng) -> (usize, usize) {
 
     let x1 = rng.gen_range(0..n);
     let x2 = (rng.gen_range(0..(n - 1)) + 1 + x1) % n;
 
     (x1, x2)
-}
\ No newline at end of file
+}
+
+/**
+ * Generate a random distance matrix with integer weights, for the tests.
+ *
+ * @param n: The number of nodes
+ * @param symmetric: Whether d(i, j) == d(j, i)
+ * @param rng: The random number generator
+ * @return: The distance matrix
+ */
+#[cfg(test)]
+pub(crate) fn random_distance_matrix(n: usize, symmetric: bool, rng: &mut impl Rng) -> DistanceMatrix {
+    let weights: Vec<f32> = (0..n * n).map(|_| rng.gen_range(1..100) as f32).collect();
+    let weight = |i: usize, j: usize| {
+        if i == j { 0.0 } else if symmetric { weights[i.min(j) * n + i.max(j)] } else { weights[i * n + j] }
+    };
+    let rows: Vec<Vec<f32>> = (0..n).map(|i| (0..n).map(|j| weight(i, j)).collect()).collect();
+    DistanceMatrix::from_rows(&rows)
+}
@@ -9,12 +9,18 @@ def read_results(data_path):
     results = dict()
     for intance in glob(join(data_path, "*")):
         instance_name = intance.split('/')[-1]
-        if instance_name in optima.keys():
-            results[instance_name] = dict()
-            for algorithm in glob(join(intance, "*.json")):
-                algorithm_name = algorithm.split('/')[-1].split('.')[0]
-                with open(algorithm, 'r') as file:
-                    results[instance_name][algorithm_name] = json.load(file)
+        results[instance_name] = dict()
+        for algorithm in glob(join(intance, "*.json")):
+            algorithm_name = algorithm.split('/')[-1].split('.')[0]
+            with open(algorithm, 'r') as file:
+                results[instance_name][algorithm_name] = json.load(file)
+        # Instances without a published optimum are scored against their lower bound
+        if instance_name not in optima:
+            bounds = [result["lower_bound"] for result in results[instance_name].values() if result.get("lower_bound") is not None]
+            if bounds:
+                optima[instance_name] = max(bounds)
+            else:
+                del results[instance_name]
     return results
 
 